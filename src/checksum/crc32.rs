@@ -0,0 +1,94 @@
+/// The reversed polynomial of the CRC-32 used by Ethernet, zlib, PNG and GPT.
+const POLYNOMIAL: u32 = 0xEDB8_8320;
+
+static TABLE: [u32; 256] = make_table(POLYNOMIAL);
+
+pub(super) const fn make_table(polynomial: u32) -> [u32; 256] {
+    let mut table = [0_u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ polynomial
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// Computes the CRC-32 of the given data in one go.
+///
+/// ```rust
+/// use kstd::checksum::crc32;
+///
+/// assert_eq!(0xCBF4_3926, crc32(b"123456789"));
+/// ```
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(data);
+    crc.finish()
+}
+
+/// Incrementally computes a CRC-32 over data that is
+/// not available as a single slice.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Crc32 {
+    state: u32,
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Crc32 {
+    pub const fn new() -> Self {
+        Self { state: !0 }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for &b in data {
+            self.state = TABLE[((self.state ^ b as u32) & 0xFF) as usize] ^ (self.state >> 8);
+        }
+    }
+
+    /// Returns the checksum of all data passed to [`Crc32::update`] so far.
+    pub fn finish(&self) -> u32 {
+        !self.state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32_empty() {
+        assert_eq!(0, crc32(&[]));
+    }
+
+    #[test]
+    fn test_crc32_check_value() {
+        assert_eq!(0xCBF4_3926, crc32(b"123456789"));
+        assert_eq!(
+            0x414F_A339,
+            crc32(b"The quick brown fox jumps over the lazy dog")
+        );
+    }
+
+    #[test]
+    fn test_crc32_incremental() {
+        let mut crc = Crc32::new();
+        crc.update(b"1234");
+        crc.update(b"56789");
+        assert_eq!(crc32(b"123456789"), crc.finish());
+    }
+}
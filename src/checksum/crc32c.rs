@@ -0,0 +1,72 @@
+use crate::checksum::crc32::make_table;
+
+/// The reversed Castagnoli polynomial, used by iSCSI, ext4 and btrfs.
+/// It detects more errors in short messages than the polynomial of [`crate::checksum::crc32`].
+const POLYNOMIAL: u32 = 0x82F6_3B78;
+
+static TABLE: [u32; 256] = make_table(POLYNOMIAL);
+
+/// Computes the CRC-32C of the given data in one go.
+///
+/// ```rust
+/// use kstd::checksum::crc32c;
+///
+/// assert_eq!(0xE306_9283, crc32c(b"123456789"));
+/// ```
+pub fn crc32c(data: &[u8]) -> u32 {
+    let mut crc = Crc32c::new();
+    crc.update(data);
+    crc.finish()
+}
+
+/// Incrementally computes a CRC-32C over data that is
+/// not available as a single slice.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Crc32c {
+    state: u32,
+}
+
+impl Default for Crc32c {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Crc32c {
+    pub const fn new() -> Self {
+        Self { state: !0 }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for &b in data {
+            self.state = TABLE[((self.state ^ b as u32) & 0xFF) as usize] ^ (self.state >> 8);
+        }
+    }
+
+    /// Returns the checksum of all data passed to [`Crc32c::update`] so far.
+    pub fn finish(&self) -> u32 {
+        !self.state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32c_check_value() {
+        assert_eq!(0, crc32c(&[]));
+        assert_eq!(0xE306_9283, crc32c(b"123456789"));
+        // iSCSI test vectors from RFC 3720
+        assert_eq!(0x8A91_36AA, crc32c(&[0_u8; 32]));
+        assert_eq!(0x62A8_AB43, crc32c(&[0xFF_u8; 32]));
+    }
+
+    #[test]
+    fn test_crc32c_incremental() {
+        let mut crc = Crc32c::new();
+        crc.update(b"1234");
+        crc.update(b"56789");
+        assert_eq!(crc32c(b"123456789"), crc.finish());
+    }
+}
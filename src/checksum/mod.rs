@@ -0,0 +1,5 @@
+pub use crc32::{crc32, Crc32};
+pub use crc32c::{crc32c, Crc32c};
+
+pub mod crc32;
+pub mod crc32c;
@@ -0,0 +1,224 @@
+use core::hash::Hash;
+
+use crate::collections::lrumap::Iter;
+use crate::collections::{CachePolicy, CacheStats, LruMap};
+
+/// A keyed cache with a fixed capacity, that evicts entries with the
+/// adaptive replacement cache algorithm (Megiddo and Modha, 2003).
+///
+/// Entries that were used once are kept in one LRU list, and entries that were
+/// used at least twice in another. The keys of entries evicted from either list
+/// are remembered in a ghost list of their own. A target size for the first list
+/// is adjusted on every insertion of a remembered key, so that the cache adapts
+/// to whether the workload favours recency or frequency. Like 2Q, this is scan
+/// resistant, but it doesn't need a tuning parameter.
+pub struct ArcMap<K, V> {
+    capacity: usize,
+    /// The target size of `t1`.
+    p: usize,
+    /// Entries that were used once.
+    t1: LruMap<K, V>,
+    /// Entries that were used at least twice.
+    t2: LruMap<K, V>,
+    /// Keys that were evicted from `t1`.
+    b1: LruMap<K, ()>,
+    /// Keys that were evicted from `t2`.
+    b2: LruMap<K, ()>,
+    stats: CacheStats,
+}
+
+impl<K, V> ArcMap<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            p: 0,
+            t1: LruMap::new(capacity),
+            t2: LruMap::new(capacity),
+            b1: LruMap::new(capacity),
+            b2: LruMap::new(capacity),
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// Returns the target size of `t1` after inserting the given key,
+    /// which grows on a hit in `b1` and shrinks on a hit in `b2`.
+    fn adapted_target(&self, key: &K) -> usize {
+        if self.b1.contains_key(key) {
+            let delta = (self.b2.len() / self.b1.len()).max(1);
+            (self.p + delta).min(self.capacity)
+        } else if self.b2.contains_key(key) {
+            let delta = (self.b1.len() / self.b2.len()).max(1);
+            self.p.saturating_sub(delta)
+        } else {
+            self.p
+        }
+    }
+
+    /// Whether the next eviction for the given key takes the least recently
+    /// used entry of `t1`, rather than that of `t2`.
+    fn evict_from_t1(&self, key: &K, target: usize) -> bool {
+        let t1 = self.t1.len();
+        self.t2.is_empty()
+            || (t1 > 0 && (t1 > target || (t1 == target && self.b2.contains_key(key))))
+    }
+
+    /// Drops the oldest ghost keys, so that `t1` and `b1` together hold at most
+    /// `capacity` keys, and all lists together at most twice the capacity.
+    fn trim_ghosts(&mut self) {
+        while self.t1.len() + self.b1.len() > self.capacity && self.b1.pop_lru().is_some() {}
+        while self.t1.len() + self.t2.len() + self.b1.len() + self.b2.len() > 2 * self.capacity {
+            if self.b2.pop_lru().is_none() && self.b1.pop_lru().is_none() {
+                break;
+            }
+        }
+    }
+}
+
+impl<K, V> CachePolicy<K, V> for ArcMap<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    type Iter<'a>
+        = core::iter::Chain<Iter<'a, K, V>, Iter<'a, K, V>>
+    where
+        Self: 'a,
+        K: 'a,
+        V: 'a;
+
+    fn get(&mut self, key: &K) -> Option<&V> {
+        if let Some(value) = self.t1.remove(key) {
+            // the second use promotes the entry
+            self.stats.hits += 1;
+            let _ = self.t2.insert(key.clone(), value);
+            return self.t2.peek(key);
+        }
+        if self.t2.contains_key(key) {
+            self.stats.hits += 1;
+            return self.t2.get(key);
+        }
+        self.stats.misses += 1;
+        None
+    }
+
+    fn peek(&self, key: &K) -> Option<&V> {
+        self.t1.peek(key).or_else(|| self.t2.peek(key))
+    }
+
+    fn insert(&mut self, key: K, value: V) -> Option<(K, V)> {
+        if let Some(old_value) = self.t1.remove(&key) {
+            let _ = self.t2.insert(key.clone(), value);
+            return Some((key, old_value));
+        }
+        if self.t2.contains_key(&key) {
+            return self.t2.insert(key, value);
+        }
+
+        if self.capacity == 0 {
+            return Some((key, value));
+        }
+
+        let target = self.adapted_target(&key);
+        let evicted = if self.is_full() {
+            self.stats.evictions += 1;
+            if self.evict_from_t1(&key, target) {
+                let (k, v) = self.t1.pop_lru().unwrap();
+                let _ = self.b1.insert(k.clone(), ());
+                Some((k, v))
+            } else {
+                let (k, v) = self.t2.pop_lru().unwrap();
+                let _ = self.b2.insert(k.clone(), ());
+                Some((k, v))
+            }
+        } else {
+            None
+        };
+        self.p = target;
+
+        if self.b1.remove(&key).is_some() || self.b2.remove(&key).is_some() {
+            let _ = self.t2.insert(key, value);
+        } else {
+            let _ = self.t1.insert(key, value);
+        }
+        self.trim_ghosts();
+        evicted
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.t1.remove(key).or_else(|| self.t2.remove(key))
+    }
+
+    fn victim(&mut self, key: &K) -> Option<(&K, &V)> {
+        if !self.is_full() || self.contains_key(key) {
+            return None;
+        }
+        if self.evict_from_t1(key, self.adapted_target(key)) {
+            self.t1.peek_lru()
+        } else {
+            self.t2.peek_lru()
+        }
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        self.t2.iter().chain(self.t1.iter())
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn len(&self) -> usize {
+        self.t1.len() + self.t2.len()
+    }
+
+    fn stats(&self) -> CacheStats {
+        CacheStats {
+            occupancy: self.len(),
+            capacity: self.capacity,
+            ..self.stats
+        }
+    }
+
+    fn reset_stats(&mut self) {
+        self.stats = CacheStats::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::collections::{ArcMap, CachePolicy};
+
+    #[test]
+    fn test_arc_map_scan_resistant() {
+        let mut map = ArcMap::new(4);
+        for i in 0..4 {
+            map.insert(i, i);
+        }
+        map.get(&0);
+        map.get(&1);
+
+        for i in 100..200 {
+            map.insert(i, i);
+        }
+        assert!(map.contains_key(&0));
+        assert!(map.contains_key(&1));
+        assert_eq!(4, map.len());
+    }
+
+    #[test]
+    fn test_arc_map_adapts_to_recency() {
+        let mut map = ArcMap::new(2);
+        map.insert(0, 0);
+        map.insert(1, 1);
+        map.get(&0);
+        // 2 evicts 1 from t1, and 1 is remembered
+        assert_eq!(Some((1, 1)), map.insert(2, 2));
+        // 1 is inserted again, which shows that t1 was too small, so the
+        // target of t1 grows, and the frequently used 0 is evicted instead of 2
+        assert_eq!(Some((0, 0)), map.insert(1, 1));
+        assert!(map.contains_key(&1));
+        assert!(map.contains_key(&2));
+    }
+}
@@ -0,0 +1,227 @@
+use alloc::vec::Vec;
+use core::hash::Hash;
+
+use hashbrown::HashMap;
+
+use crate::collections::{CachePolicy, CacheStats};
+
+/// A keyed cache with a fixed capacity, that evicts entries with the
+/// CLOCK algorithm.
+///
+/// The entries are arranged in a circle, and every entry has a reference bit
+/// that is set when the entry is used. To find an entry to evict, a hand sweeps
+/// over the circle, clearing the reference bits it passes, until it finds an
+/// entry whose bit is not set. This approximates LRU, but a use of an entry
+/// only sets a bit instead of reordering a list.
+pub struct ClockMap<K, V> {
+    capacity: usize,
+    index: HashMap<K, usize>,
+    slots: Vec<Option<Slot<K, V>>>,
+    free: Vec<usize>,
+    hand: usize,
+    stats: CacheStats,
+}
+
+struct Slot<K, V> {
+    key: K,
+    value: V,
+    referenced: bool,
+}
+
+impl<K, V> ClockMap<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            index: HashMap::with_capacity(capacity),
+            slots: Vec::with_capacity(capacity),
+            free: Vec::new(),
+            hand: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    fn slot(&self, slot: usize) -> &Slot<K, V> {
+        self.slots[slot].as_ref().unwrap()
+    }
+
+    /// Moves the hand to the next entry that is not referenced, clearing
+    /// the reference bits on the way, and returns its slot. Must only be
+    /// called if the map is full, so that every slot is occupied.
+    fn advance(&mut self) -> usize {
+        loop {
+            let slot = self.slots[self.hand].as_mut().unwrap();
+            if !slot.referenced {
+                return self.hand;
+            }
+            slot.referenced = false;
+            self.hand = (self.hand + 1) % self.slots.len();
+        }
+    }
+}
+
+impl<K, V> CachePolicy<K, V> for ClockMap<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    type Iter<'a>
+        = Iter<'a, K, V>
+    where
+        Self: 'a,
+        K: 'a,
+        V: 'a;
+
+    fn get(&mut self, key: &K) -> Option<&V> {
+        let Some(&slot) = self.index.get(key) else {
+            self.stats.misses += 1;
+            return None;
+        };
+        self.stats.hits += 1;
+        let slot = self.slots[slot].as_mut().unwrap();
+        slot.referenced = true;
+        Some(&slot.value)
+    }
+
+    fn peek(&self, key: &K) -> Option<&V> {
+        self.index.get(key).map(|&slot| &self.slot(slot).value)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.index.contains_key(key)
+    }
+
+    fn insert(&mut self, key: K, value: V) -> Option<(K, V)> {
+        if let Some(&slot) = self.index.get(&key) {
+            let slot = self.slots[slot].as_mut().unwrap();
+            slot.referenced = true;
+            let old_key = core::mem::replace(&mut slot.key, key);
+            let old_value = core::mem::replace(&mut slot.value, value);
+            return Some((old_key, old_value));
+        }
+
+        if self.capacity == 0 {
+            return Some((key, value));
+        }
+
+        let new = Slot {
+            key: key.clone(),
+            value,
+            referenced: false,
+        };
+        if self.is_full() {
+            let victim = self.advance();
+            let old = self.slots[victim].replace(new).unwrap();
+            self.index.remove(&old.key);
+            self.index.insert(key, victim);
+            // the new entry gets a full turn of the hand before it can be evicted
+            self.hand = (victim + 1) % self.slots.len();
+            self.stats.evictions += 1;
+            return Some((old.key, old.value));
+        }
+
+        let slot = match self.free.pop() {
+            Some(slot) => {
+                self.slots[slot] = Some(new);
+                slot
+            }
+            None => {
+                self.slots.push(Some(new));
+                self.slots.len() - 1
+            }
+        };
+        self.index.insert(key, slot);
+        None
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        let slot = self.index.remove(key)?;
+        self.free.push(slot);
+        self.slots[slot].take().map(|slot| slot.value)
+    }
+
+    fn victim(&mut self, key: &K) -> Option<(&K, &V)> {
+        if !self.is_full() || self.contains_key(key) || self.capacity == 0 {
+            return None;
+        }
+        let victim = self.advance();
+        let slot = self.slot(victim);
+        Some((&slot.key, &slot.value))
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        Iter {
+            slots: self.slots.iter(),
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    fn stats(&self) -> CacheStats {
+        CacheStats {
+            occupancy: self.len(),
+            capacity: self.capacity,
+            ..self.stats
+        }
+    }
+
+    fn reset_stats(&mut self) {
+        self.stats = CacheStats::default();
+    }
+}
+
+pub struct Iter<'a, K, V> {
+    slots: core::slice::Iter<'a, Option<Slot<K, V>>>,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.slots
+            .by_ref()
+            .flatten()
+            .next()
+            .map(|slot| (&slot.key, &slot.value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::collections::{CachePolicy, ClockMap};
+
+    #[test]
+    fn test_clock_map_second_chance() {
+        let mut map = ClockMap::new(3);
+        for i in 0..3 {
+            map.insert(i, i);
+        }
+        map.get(&0);
+        // 0 is referenced, so the hand skips it and clears its bit
+        assert_eq!(Some((&1, &1)), map.victim(&3));
+        assert_eq!(Some((1, 1)), map.insert(3, 3));
+        assert_eq!(Some((2, 2)), map.insert(4, 4));
+        // the hand went around, and now 0 is no longer referenced
+        assert_eq!(Some((0, 0)), map.insert(5, 5));
+        assert!(map.contains_key(&3));
+    }
+
+    #[test]
+    fn test_clock_map_remove_reuses_slot() {
+        let mut map = ClockMap::new(2);
+        map.insert(1, 1);
+        map.insert(2, 2);
+        assert_eq!(Some(1), map.remove(&1));
+        assert_eq!(None, map.victim(&3));
+        assert_eq!(None, map.insert(3, 3));
+        assert_eq!(2, map.len());
+        assert_eq!(Some(&3), map.peek(&3));
+    }
+}
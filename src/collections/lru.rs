@@ -1,6 +1,8 @@
 use alloc::boxed::Box;
 use alloc::collections::VecDeque;
 
+use crate::collections::CacheStats;
+
 /// Implements an least recently used cache. It has a fixed size and will remove
 /// the least recently used item when the size is reached. The least recently used
 /// item is always the first item in the queue. The item is removed by calling the
@@ -10,6 +12,7 @@ pub struct LruCache<V> {
     max_size: usize,
     data: VecDeque<V>,
     on_evict: Box<dyn Fn(V)>,
+    stats: CacheStats,
 }
 
 impl<V> LruCache<V> {
@@ -22,6 +25,7 @@ impl<V> LruCache<V> {
             max_size: size,
             data: VecDeque::with_capacity(size),
             on_evict: Box::new(on_evict),
+            stats: CacheStats::default(),
         }
     }
 
@@ -30,16 +34,19 @@ impl<V> LruCache<V> {
         P: FnMut(&V) -> bool,
     {
         if let Some(position) = self.data.iter().position(predicate) {
+            self.stats.hits += 1;
             let item = self.data.remove(position).unwrap();
             self.data.push_front(item);
             return Some(&self.data[0]);
         }
+        self.stats.misses += 1;
         None
     }
 
     pub fn insert(&mut self, item: V) {
         if self.data.len() >= self.max_size {
             if let Some(item) = self.data.pop_back() {
+                self.stats.evictions += 1;
                 self.evict(item);
             }
         }
@@ -51,6 +58,21 @@ impl<V> LruCache<V> {
         (self.on_evict)(item)
     }
 
+    /// Returns statistics about the usage of this cache since it was created,
+    /// or since the last call to [`LruCache::reset_stats`]. Calls to
+    /// [`LruCache::find`] count as lookups.
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            occupancy: self.data.len(),
+            capacity: self.max_size,
+            ..self.stats
+        }
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = CacheStats::default();
+    }
+
     pub fn len(&self) -> usize {
         self.data.len()
     }
@@ -70,7 +92,7 @@ impl<V> Drop for LruCache<V> {
 
 #[cfg(test)]
 mod tests {
-    use crate::collections::VecDeque;
+    use crate::collections::{CacheStats, VecDeque};
     use crate::sync::atomic::{AtomicUsize, Ordering};
     use crate::sync::Arc;
 
@@ -136,4 +158,31 @@ mod tests {
 
         assert_eq!(10, evict_count.load(Ordering::SeqCst));
     }
+
+    #[test]
+    fn test_lru_stats() {
+        let mut lru = LruCache::<u8>::new(3);
+        for i in 0_u8..5 {
+            lru.insert(i);
+        }
+        assert!(lru.find(|&v| v == 4).is_some());
+        assert!(lru.find(|&v| v == 0).is_none());
+        assert!(lru.find(|&v| v == 3).is_some());
+        assert_eq!(
+            CacheStats {
+                hits: 2,
+                misses: 1,
+                evictions: 2,
+                writebacks: 0,
+                dirty: 0,
+                occupancy: 3,
+                capacity: 3,
+            },
+            lru.stats()
+        );
+
+        lru.reset_stats();
+        assert_eq!(0, lru.stats().hits);
+        assert_eq!(3, lru.stats().occupancy);
+    }
 }
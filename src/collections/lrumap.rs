@@ -0,0 +1,493 @@
+use alloc::vec::Vec;
+use core::hash::Hash;
+
+use hashbrown::HashMap;
+
+use crate::collections::{CachePolicy, CacheStats};
+
+const NIL: usize = usize::MAX;
+
+/// A keyed least recently used cache with a fixed capacity.
+///
+/// Lookups, insertions and removals are O(1). Keys are indexed with a
+/// hash map that points into a slab of entries, and the entries form
+/// an intrusive doubly linked list that is ordered by recency, the most
+/// recently used entry being the head of the list.
+///
+/// Unlike [`LruCache`](crate::collections::LruCache), evicted entries are
+/// returned to the caller of [`LruMap::insert`] instead of being passed to
+/// an eviction function.
+pub struct LruMap<K, V> {
+    capacity: usize,
+    index: HashMap<K, usize>,
+    entries: Vec<Option<Entry<K, V>>>,
+    free: Vec<usize>,
+    head: usize,
+    tail: usize,
+    stats: CacheStats,
+}
+
+struct Entry<K, V> {
+    key: K,
+    value: V,
+    prev: usize,
+    next: usize,
+}
+
+impl<K, V> LruMap<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            index: HashMap::with_capacity(capacity),
+            entries: Vec::with_capacity(capacity),
+            free: Vec::new(),
+            head: NIL,
+            tail: NIL,
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// Returns a reference to the value of the given key and marks
+    /// the entry as the most recently used one.
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        self.get_mut(key).map(|v| &*v)
+    }
+
+    /// Returns a mutable reference to the value of the given key and
+    /// marks the entry as the most recently used one.
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let slot = match self.index.get(key) {
+            Some(&slot) => slot,
+            None => {
+                self.stats.misses += 1;
+                return None;
+            }
+        };
+        self.stats.hits += 1;
+        self.unlink(slot);
+        self.push_front(slot);
+        Some(&mut self.entry_mut(slot).value)
+    }
+
+    /// Returns a reference to the value of the given key without
+    /// marking the entry as used.
+    pub fn peek(&self, key: &K) -> Option<&V> {
+        self.index.get(key).map(|&slot| &self.entry(slot).value)
+    }
+
+    /// Returns a mutable reference to the value of the given key without
+    /// marking the entry as used.
+    pub fn peek_mut(&mut self, key: &K) -> Option<&mut V> {
+        let slot = *self.index.get(key)?;
+        Some(&mut self.entry_mut(slot).value)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.index.contains_key(key)
+    }
+
+    /// Inserts the given value with the given key into this map as the
+    /// most recently used entry.
+    ///
+    /// If the key is already present, the value is replaced and the old
+    /// entry is returned. Otherwise, if the map is full, the least recently
+    /// used entry is evicted and returned.
+    pub fn insert(&mut self, key: K, value: V) -> Option<(K, V)> {
+        if let Some(&slot) = self.index.get(&key) {
+            self.unlink(slot);
+            self.push_front(slot);
+            let entry = self.entry_mut(slot);
+            let old_key = core::mem::replace(&mut entry.key, key);
+            let old_value = core::mem::replace(&mut entry.value, value);
+            return Some((old_key, old_value));
+        }
+
+        if self.capacity == 0 {
+            return Some((key, value));
+        }
+
+        let evicted = if self.is_full() {
+            self.stats.evictions += 1;
+            self.pop_lru()
+        } else {
+            None
+        };
+
+        let entry = Entry {
+            key: key.clone(),
+            value,
+            prev: NIL,
+            next: NIL,
+        };
+        let slot = match self.free.pop() {
+            Some(slot) => {
+                self.entries[slot] = Some(entry);
+                slot
+            }
+            None => {
+                self.entries.push(Some(entry));
+                self.entries.len() - 1
+            }
+        };
+        self.index.insert(key, slot);
+        self.push_front(slot);
+
+        evicted
+    }
+
+    /// Removes the entry with the given key from this map and
+    /// returns its value.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let slot = self.index.remove(key)?;
+        Some(self.remove_slot(slot).value)
+    }
+
+    /// Returns the entry that will be evicted by the next [`LruMap::insert`]
+    /// if the map is full, without marking it as used.
+    pub fn peek_lru(&self) -> Option<(&K, &V)> {
+        if self.tail == NIL {
+            return None;
+        }
+        let entry = self.entry(self.tail);
+        Some((&entry.key, &entry.value))
+    }
+
+    /// Removes the least recently used entry from this map and returns it.
+    pub fn pop_lru(&mut self) -> Option<(K, V)> {
+        if self.tail == NIL {
+            return None;
+        }
+        let entry = self.remove_slot(self.tail);
+        self.index.remove(&entry.key);
+        Some((entry.key, entry.value))
+    }
+
+    /// Returns an iterator over all entries in this map, starting
+    /// with the most recently used one. Iterating does not count
+    /// as a use of the entries.
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            map: self,
+            next: self.head,
+            remaining: self.len(),
+        }
+    }
+
+    /// Removes all entries from this map.
+    pub fn clear(&mut self) {
+        self.index.clear();
+        self.entries.clear();
+        self.free.clear();
+        self.head = NIL;
+        self.tail = NIL;
+    }
+
+    /// Returns statistics about the usage of this map since it was created,
+    /// or since the last call to [`LruMap::reset_stats`]. Calls to [`LruMap::get`]
+    /// and [`LruMap::get_mut`] count as lookups, while [`LruMap::peek`] and
+    /// [`LruMap::contains_key`] don't.
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            occupancy: self.len(),
+            capacity: self.capacity,
+            ..self.stats
+        }
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = CacheStats::default();
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() >= self.capacity
+    }
+
+    fn entry(&self, slot: usize) -> &Entry<K, V> {
+        self.entries[slot].as_ref().unwrap()
+    }
+
+    fn entry_mut(&mut self, slot: usize) -> &mut Entry<K, V> {
+        self.entries[slot].as_mut().unwrap()
+    }
+
+    /// Removes the entry in the given slot from the recency list and the slab,
+    /// but not from the index.
+    fn remove_slot(&mut self, slot: usize) -> Entry<K, V> {
+        self.unlink(slot);
+        self.free.push(slot);
+        self.entries[slot].take().unwrap()
+    }
+
+    fn unlink(&mut self, slot: usize) {
+        let (prev, next) = {
+            let entry = self.entry(slot);
+            (entry.prev, entry.next)
+        };
+        if prev == NIL {
+            self.head = next;
+        } else {
+            self.entry_mut(prev).next = next;
+        }
+        if next == NIL {
+            self.tail = prev;
+        } else {
+            self.entry_mut(next).prev = prev;
+        }
+    }
+
+    fn push_front(&mut self, slot: usize) {
+        let head = self.head;
+        {
+            let entry = self.entry_mut(slot);
+            entry.prev = NIL;
+            entry.next = head;
+        }
+        if head == NIL {
+            self.tail = slot;
+        } else {
+            self.entry_mut(head).prev = slot;
+        }
+        self.head = slot;
+    }
+}
+
+impl<K, V> CachePolicy<K, V> for LruMap<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    type Iter<'a>
+        = Iter<'a, K, V>
+    where
+        Self: 'a,
+        K: 'a,
+        V: 'a;
+
+    fn get(&mut self, key: &K) -> Option<&V> {
+        LruMap::get(self, key)
+    }
+
+    fn peek(&self, key: &K) -> Option<&V> {
+        LruMap::peek(self, key)
+    }
+
+    fn contains_key(&self, key: &K) -> bool {
+        LruMap::contains_key(self, key)
+    }
+
+    fn insert(&mut self, key: K, value: V) -> Option<(K, V)> {
+        LruMap::insert(self, key, value)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        LruMap::remove(self, key)
+    }
+
+    fn victim(&mut self, key: &K) -> Option<(&K, &V)> {
+        if !self.is_full() || self.contains_key(key) {
+            return None;
+        }
+        self.peek_lru()
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        LruMap::iter(self)
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn len(&self) -> usize {
+        LruMap::len(self)
+    }
+
+    fn stats(&self) -> CacheStats {
+        LruMap::stats(self)
+    }
+
+    fn reset_stats(&mut self) {
+        LruMap::reset_stats(self)
+    }
+}
+
+pub struct Iter<'a, K, V> {
+    map: &'a LruMap<K, V>,
+    next: usize,
+    remaining: usize,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next == NIL {
+            return None;
+        }
+        let entry = self.map.entries[self.next].as_ref().unwrap();
+        self.next = entry.next;
+        self.remaining -= 1;
+        Some((&entry.key, &entry.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use super::LruMap;
+    use crate::collections::CacheStats;
+
+    fn keys(map: &LruMap<u32, u32>) -> Vec<u32> {
+        map.iter().map(|(&k, _)| k).collect()
+    }
+
+    #[test]
+    fn test_lru_map_new_is_empty() {
+        let map = LruMap::<u32, u32>::new(10);
+        assert!(map.is_empty());
+        assert_eq!(None, map.peek_lru());
+        assert_eq!(Vec::<u32>::new(), keys(&map));
+    }
+
+    #[test]
+    fn test_lru_map_insert_get() {
+        let mut map = LruMap::new(10);
+        for i in 0..5 {
+            assert_eq!(None, map.insert(i, i * 10));
+        }
+        assert_eq!(vec![4, 3, 2, 1, 0], keys(&map));
+        assert_eq!(Some(&20), map.get(&2));
+        assert_eq!(vec![2, 4, 3, 1, 0], keys(&map));
+        assert_eq!(Some(&0), map.peek(&0));
+        assert_eq!(vec![2, 4, 3, 1, 0], keys(&map));
+        *map.get_mut(&0).unwrap() = 7;
+        assert_eq!(vec![0, 2, 4, 3, 1], keys(&map));
+        assert_eq!(Some(&7), map.peek(&0));
+        assert_eq!(None, map.get(&5));
+    }
+
+    #[test]
+    fn test_lru_map_insert_existing() {
+        let mut map = LruMap::new(2);
+        map.insert(1, 1);
+        map.insert(2, 2);
+        assert_eq!(Some((1, 1)), map.insert(1, 3));
+        assert_eq!(2, map.len());
+        assert_eq!(vec![1, 2], keys(&map));
+        assert_eq!(Some(&3), map.peek(&1));
+    }
+
+    #[test]
+    fn test_lru_map_evict() {
+        let mut map = LruMap::new(3);
+        for i in 0..3 {
+            assert_eq!(None, map.insert(i, i));
+        }
+        map.get(&0);
+        assert_eq!(Some((&1, &1)), map.peek_lru());
+        assert_eq!(Some((1, 1)), map.insert(3, 3));
+        assert_eq!(Some((2, 2)), map.insert(4, 4));
+        assert_eq!(vec![4, 3, 0], keys(&map));
+        assert!(!map.contains_key(&1));
+        assert!(map.contains_key(&0));
+    }
+
+    #[test]
+    fn test_lru_map_remove() {
+        let mut map = LruMap::new(4);
+        for i in 0..4 {
+            map.insert(i, i);
+        }
+        assert_eq!(Some(2), map.remove(&2));
+        assert_eq!(None, map.remove(&2));
+        assert_eq!(vec![3, 1, 0], keys(&map));
+        assert_eq!(Some(0), map.remove(&0));
+        assert_eq!(Some(3), map.remove(&3));
+        assert_eq!(vec![1], keys(&map));
+        // the freed slots are reused
+        map.insert(5, 5);
+        map.insert(6, 6);
+        assert_eq!(vec![6, 5, 1], keys(&map));
+        assert_eq!(Some((1, 1)), map.pop_lru());
+        assert_eq!(Some((5, 5)), map.pop_lru());
+        assert_eq!(Some((6, 6)), map.pop_lru());
+        assert_eq!(None, map.pop_lru());
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn test_lru_map_stats() {
+        let mut map = LruMap::new(2);
+        for i in 0..4 {
+            map.insert(i, i);
+        }
+        map.get(&3);
+        map.get_mut(&2);
+        map.get(&0);
+        map.peek(&1);
+        assert_eq!(
+            CacheStats {
+                hits: 2,
+                misses: 1,
+                evictions: 2,
+                writebacks: 0,
+                dirty: 0,
+                occupancy: 2,
+                capacity: 2,
+            },
+            map.stats()
+        );
+
+        map.reset_stats();
+        assert_eq!(
+            CacheStats {
+                occupancy: 2,
+                capacity: 2,
+                ..CacheStats::default()
+            },
+            map.stats()
+        );
+    }
+
+    #[test]
+    fn test_lru_map_zero_capacity() {
+        let mut map = LruMap::new(0);
+        assert_eq!(Some((1, 1)), map.insert(1, 1));
+        assert!(map.is_empty());
+    }
+
+    #[test]
+    fn test_lru_map_many() {
+        let limit = 10_000;
+        let mut map = LruMap::new(limit / 2);
+        for i in 0..limit {
+            let evicted = map.insert(i, i);
+            if i < limit / 2 {
+                assert_eq!(None, evicted);
+            } else {
+                assert_eq!(Some((i - limit / 2, i - limit / 2)), evicted);
+            }
+        }
+        assert_eq!(limit / 2, map.len());
+    }
+}
@@ -1,6 +1,18 @@
 pub use alloc::collections::*;
+pub use arc::ArcMap;
+pub use clock::ClockMap;
 pub use deltaq::DeltaQueue;
 pub use lru::LruCache;
+pub use lrumap::LruMap;
+pub use policy::CachePolicy;
+pub use stats::CacheStats;
+pub use twoq::TwoQueueMap;
 
+pub mod arc;
+pub mod clock;
 pub mod deltaq;
 pub mod lru;
+pub mod lrumap;
+pub mod policy;
+pub mod stats;
+pub mod twoq;
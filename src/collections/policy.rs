@@ -0,0 +1,137 @@
+use crate::collections::CacheStats;
+
+/// A keyed cache with a fixed capacity, that decides on its own which entry
+/// to evict when a new one is inserted into the full cache.
+///
+/// Implementations are [`LruMap`](crate::collections::LruMap) (least recently used),
+/// [`ClockMap`](crate::collections::ClockMap) (CLOCK, an approximation of LRU),
+/// [`TwoQueueMap`](crate::collections::TwoQueueMap) (2Q) and
+/// [`ArcMap`](crate::collections::ArcMap) (adaptive replacement cache).
+/// The latter two are scan resistant, meaning that a single pass over
+/// many entries doesn't evict the entries that are used repeatedly.
+pub trait CachePolicy<K, V> {
+    type Iter<'a>: Iterator<Item = (&'a K, &'a V)>
+    where
+        Self: 'a,
+        K: 'a,
+        V: 'a;
+
+    /// Returns a reference to the value of the given key, and records
+    /// the access as a use of the entry.
+    fn get(&mut self, key: &K) -> Option<&V>;
+
+    /// Returns a reference to the value of the given key without
+    /// recording a use of the entry.
+    fn peek(&self, key: &K) -> Option<&V>;
+
+    fn contains_key(&self, key: &K) -> bool {
+        self.peek(key).is_some()
+    }
+
+    /// Inserts the given value with the given key into this cache.
+    ///
+    /// If the key is already present, the value is replaced and the old
+    /// entry is returned. Otherwise, if the cache is full, an entry is
+    /// evicted and returned. If the capacity is zero, the given entry
+    /// itself is returned.
+    fn insert(&mut self, key: K, value: V) -> Option<(K, V)>;
+
+    /// Removes the entry with the given key from this cache and
+    /// returns its value.
+    fn remove(&mut self, key: &K) -> Option<V>;
+
+    /// Returns the entry that will be evicted if the given key is inserted
+    /// next, or `None` if inserting the key doesn't evict anything.
+    ///
+    /// This takes `&mut self`, because some policies have to update their
+    /// bookkeeping to find the victim. This is not a use of any entry.
+    fn victim(&mut self, key: &K) -> Option<(&K, &V)>;
+
+    /// Returns an iterator over all entries in this cache. The order
+    /// depends on the policy. Iterating does not count as a use of
+    /// the entries.
+    fn iter(&self) -> Self::Iter<'_>;
+
+    fn capacity(&self) -> usize;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn is_full(&self) -> bool {
+        self.len() >= self.capacity()
+    }
+
+    /// Returns statistics about the usage of this cache. Calls to
+    /// [`CachePolicy::get`] count as lookups.
+    fn stats(&self) -> CacheStats;
+
+    fn reset_stats(&mut self);
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use crate::collections::{ArcMap, CachePolicy, ClockMap, LruMap, TwoQueueMap};
+
+    /// Checks the behaviour that every policy must have, regardless of
+    /// which entries it chooses to evict.
+    fn check_policy<P>(mut cache: P)
+    where
+        P: CachePolicy<u32, u32>,
+    {
+        let capacity = cache.capacity() as u32;
+        assert!(cache.is_empty());
+        for i in 0..capacity {
+            assert_eq!(None, cache.victim(&i));
+            assert_eq!(None, cache.insert(i, i));
+        }
+        assert!(cache.is_full());
+        assert_eq!(Some(&0), cache.get(&0));
+        assert_eq!(Some((0, 0)), cache.insert(0, 10));
+        assert_eq!(None, cache.victim(&0));
+
+        for i in capacity..capacity * 4 {
+            let (&key, &value) = cache.victim(&i).unwrap();
+            assert_eq!(Some((key, value)), cache.insert(i, i));
+            assert!(!cache.contains_key(&key));
+            assert_eq!(capacity as usize, cache.len());
+        }
+
+        let mut keys: Vec<u32> = cache.iter().map(|(&k, _)| k).collect();
+        keys.sort();
+        keys.dedup();
+        assert_eq!(capacity as usize, keys.len());
+        for key in keys {
+            assert!(cache.remove(&key).is_some());
+        }
+        assert!(cache.is_empty());
+        assert_eq!(None, cache.victim(&0));
+        assert_eq!(1, cache.stats().hits);
+    }
+
+    #[test]
+    fn test_policies() {
+        for capacity in [1, 2, 3, 8] {
+            check_policy(LruMap::new(capacity));
+            check_policy(ClockMap::new(capacity));
+            check_policy(TwoQueueMap::new(capacity));
+            check_policy(ArcMap::new(capacity));
+        }
+    }
+
+    #[test]
+    fn test_policies_zero_capacity() {
+        fn check<P: CachePolicy<u32, u32>>(mut cache: P) {
+            assert_eq!(Some((1, 1)), cache.insert(1, 1));
+            assert!(cache.is_empty());
+        }
+        check(LruMap::new(0));
+        check(ClockMap::new(0));
+        check(TwoQueueMap::new(0));
+        check(ArcMap::new(0));
+    }
+}
@@ -0,0 +1,40 @@
+use core::fmt::{Display, Formatter};
+
+/// Statistics about the usage of a cache.
+///
+/// Not every cache tracks every value. Caches that don't write anything
+/// back, like [`LruCache`](crate::collections::LruCache), always report
+/// zero writebacks and dirty entries.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct CacheStats {
+    /// The number of lookups that found the entry in the cache.
+    pub hits: usize,
+    /// The number of lookups that did not find the entry in the cache.
+    pub misses: usize,
+    /// The number of entries that were removed to make room for new ones.
+    pub evictions: usize,
+    /// The number of modified entries that were written back to the backing store.
+    pub writebacks: usize,
+    /// The number of entries that are modified, but not yet written back.
+    pub dirty: usize,
+    /// The number of entries currently in the cache.
+    pub occupancy: usize,
+    /// The maximum number of entries in the cache.
+    pub capacity: usize,
+}
+
+impl Display for CacheStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{}/{} entries, {} dirty, {} hits, {} misses, {} evictions, {} writebacks",
+            self.occupancy,
+            self.capacity,
+            self.dirty,
+            self.hits,
+            self.misses,
+            self.evictions,
+            self.writebacks
+        )
+    }
+}
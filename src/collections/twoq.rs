@@ -0,0 +1,189 @@
+use core::hash::Hash;
+
+use crate::collections::lrumap::Iter;
+use crate::collections::{CachePolicy, CacheStats, LruMap};
+
+/// A keyed cache with a fixed capacity, that evicts entries with the
+/// 2Q algorithm (Johnson and Shasha, 1994).
+///
+/// New entries are put into a FIFO queue that holds up to a quarter of the
+/// capacity, and uses of these entries don't change their position. When an
+/// entry leaves the queue, its key is remembered in a ghost list. Only if an
+/// entry is inserted again while its key is still remembered, it goes into the
+/// main LRU list. Entries that are only used in a short burst, like during a
+/// scan, therefore never displace the entries in the main list.
+pub struct TwoQueueMap<K, V> {
+    capacity: usize,
+    /// The maximum number of entries in `a1in` before it is preferred for eviction.
+    kin: usize,
+    /// Entries that were inserted recently, in FIFO order.
+    a1in: LruMap<K, V>,
+    /// Keys that were evicted from `a1in`.
+    a1out: LruMap<K, ()>,
+    /// Entries that were inserted while their key was in `a1out`.
+    am: LruMap<K, V>,
+    stats: CacheStats,
+}
+
+impl<K, V> TwoQueueMap<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            kin: (capacity / 4).max(1),
+            a1in: LruMap::new(capacity),
+            a1out: LruMap::new((capacity / 2).max(1)),
+            am: LruMap::new(capacity),
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// Whether the next eviction takes the oldest entry of `a1in`,
+    /// rather than the least recently used entry of `am`.
+    fn evict_from_a1in(&self) -> bool {
+        self.a1in.len() > self.kin || self.am.is_empty()
+    }
+}
+
+impl<K, V> CachePolicy<K, V> for TwoQueueMap<K, V>
+where
+    K: Hash + Eq + Clone,
+{
+    type Iter<'a>
+        = core::iter::Chain<Iter<'a, K, V>, Iter<'a, K, V>>
+    where
+        Self: 'a,
+        K: 'a,
+        V: 'a;
+
+    fn get(&mut self, key: &K) -> Option<&V> {
+        if self.am.contains_key(key) {
+            self.stats.hits += 1;
+            return self.am.get(key);
+        }
+        // uses of entries in a1in don't change their position
+        let value = self.a1in.peek(key);
+        if value.is_some() {
+            self.stats.hits += 1;
+        } else {
+            self.stats.misses += 1;
+        }
+        value
+    }
+
+    fn peek(&self, key: &K) -> Option<&V> {
+        self.am.peek(key).or_else(|| self.a1in.peek(key))
+    }
+
+    fn insert(&mut self, key: K, value: V) -> Option<(K, V)> {
+        if self.am.contains_key(&key) {
+            return self.am.insert(key, value);
+        }
+        if let Some(old) = self.a1in.peek_mut(&key) {
+            let old_value = core::mem::replace(old, value);
+            return Some((key, old_value));
+        }
+
+        if self.capacity == 0 {
+            return Some((key, value));
+        }
+
+        let evicted = if self.is_full() {
+            self.stats.evictions += 1;
+            if self.evict_from_a1in() {
+                let (k, v) = self.a1in.pop_lru().unwrap();
+                let _ = self.a1out.insert(k.clone(), ());
+                Some((k, v))
+            } else {
+                self.am.pop_lru()
+            }
+        } else {
+            None
+        };
+
+        if self.a1out.remove(&key).is_some() {
+            let _ = self.am.insert(key, value);
+        } else {
+            let _ = self.a1in.insert(key, value);
+        }
+        evicted
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.am.remove(key).or_else(|| self.a1in.remove(key))
+    }
+
+    fn victim(&mut self, key: &K) -> Option<(&K, &V)> {
+        if !self.is_full() || self.contains_key(key) {
+            return None;
+        }
+        if self.evict_from_a1in() {
+            self.a1in.peek_lru()
+        } else {
+            self.am.peek_lru()
+        }
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        self.am.iter().chain(self.a1in.iter())
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    fn len(&self) -> usize {
+        self.a1in.len() + self.am.len()
+    }
+
+    fn stats(&self) -> CacheStats {
+        CacheStats {
+            occupancy: self.len(),
+            capacity: self.capacity,
+            ..self.stats
+        }
+    }
+
+    fn reset_stats(&mut self) {
+        self.stats = CacheStats::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::collections::{CachePolicy, TwoQueueMap};
+
+    #[test]
+    fn test_two_queue_map_scan_resistant() {
+        let mut map = TwoQueueMap::new(8);
+        for i in 0..8 {
+            map.insert(i, i);
+        }
+        // pushes 0 and 1 out of a1in into the ghost list
+        map.insert(8, 8);
+        map.insert(9, 9);
+        // inserted again while remembered, so they go into the main list
+        map.insert(0, 0);
+        map.insert(1, 1);
+
+        for i in 100..200 {
+            map.insert(i, i);
+            map.get(&i);
+        }
+        assert!(map.contains_key(&0));
+        assert!(map.contains_key(&1));
+        assert_eq!(8, map.len());
+    }
+
+    #[test]
+    fn test_two_queue_map_a1in_is_fifo() {
+        let mut map = TwoQueueMap::new(4);
+        for i in 0..4 {
+            map.insert(i, i);
+        }
+        assert_eq!(Some(&0), map.get(&0));
+        assert_eq!(Some((0, 0)), map.insert(4, 4));
+    }
+}
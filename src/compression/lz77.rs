@@ -0,0 +1,175 @@
+//! A simple byte oriented LZ77 format, that favours a small and fast
+//! decoder over the compression ratio.
+//!
+//! The compressed data is a sequence of tokens, each starting with a tag byte:
+//!
+//! * `0x00..=0x7F`: `tag + 1` literal bytes follow, which are copied to the output.
+//! * `0x80..=0xFF`: a match of `(tag & 0x7F) + 3` bytes, followed by the distance
+//!   back into the output as a little endian `u16`. The match may overlap the
+//!   bytes it produces, which encodes runs.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::io::{Error, Result};
+
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 0x7F + MIN_MATCH;
+const MAX_LITERALS: usize = 0x80;
+const MAX_DISTANCE: usize = u16::MAX as usize;
+const HASH_BITS: u32 = 12;
+
+fn hash(data: &[u8]) -> usize {
+    let value = u32::from_le_bytes([data[0], data[1], data[2], 0]);
+    (value.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize
+}
+
+fn push_literals(out: &mut Vec<u8>, literals: &[u8]) {
+    for chunk in literals.chunks(MAX_LITERALS) {
+        out.push((chunk.len() - 1) as u8);
+        out.extend_from_slice(chunk);
+    }
+}
+
+/// Compresses the given data. Incompressible data grows by one byte
+/// for every 128 bytes.
+///
+/// ```
+/// use kstd::compression::{compress, decompress};
+///
+/// let data = b"abcabcabcabcabcabcabcabc";
+/// let compressed = compress(data);
+/// assert!(compressed.len() < data.len());
+///
+/// let mut decompressed = [0_u8; 24];
+/// assert_eq!(Ok(24), decompress(&compressed, &mut decompressed));
+/// assert_eq!(data, &decompressed);
+/// ```
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    // the most recent position of every hashed sequence of `MIN_MATCH` bytes
+    let mut table = vec![usize::MAX; 1 << HASH_BITS];
+    let mut out = Vec::new();
+    let mut literals = 0;
+    let mut i = 0;
+    while i + MIN_MATCH <= input.len() {
+        let h = hash(&input[i..]);
+        let candidate = core::mem::replace(&mut table[h], i);
+        if candidate == usize::MAX
+            || i - candidate > MAX_DISTANCE
+            || input[candidate..candidate + MIN_MATCH] != input[i..i + MIN_MATCH]
+        {
+            i += 1;
+            continue;
+        }
+
+        let mut len = MIN_MATCH;
+        while len < MAX_MATCH && i + len < input.len() && input[candidate + len] == input[i + len] {
+            len += 1;
+        }
+        push_literals(&mut out, &input[literals..i]);
+        out.push(0x80 | (len - MIN_MATCH) as u8);
+        out.extend_from_slice(&((i - candidate) as u16).to_le_bytes());
+        i += len;
+        literals = i;
+    }
+    push_literals(&mut out, &input[literals..]);
+    out
+}
+
+/// Decompresses the given data into the given buffer, and returns the number
+/// of bytes that were written. Fails with [`Error::DecodeError`] if the data is
+/// malformed, or doesn't fit into the buffer.
+pub fn decompress(input: &[u8], output: &mut [u8]) -> Result<usize> {
+    let mut i = 0;
+    let mut o = 0;
+    while i < input.len() {
+        let tag = input[i] as usize;
+        i += 1;
+        if tag < 0x80 {
+            let len = tag + 1;
+            if i + len > input.len() || o + len > output.len() {
+                return Err(Error::DecodeError);
+            }
+            output[o..o + len].copy_from_slice(&input[i..i + len]);
+            i += len;
+            o += len;
+        } else {
+            let len = (tag & 0x7F) + MIN_MATCH;
+            if i + 2 > input.len() {
+                return Err(Error::DecodeError);
+            }
+            let distance = u16::from_le_bytes([input[i], input[i + 1]]) as usize;
+            i += 2;
+            if distance == 0 || distance > o || o + len > output.len() {
+                return Err(Error::DecodeError);
+            }
+            // byte by byte, since the match may overlap its own output
+            for k in o..o + len {
+                output[k] = output[k - distance];
+            }
+            o += len;
+        }
+    }
+    Ok(o)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use crate::compression::{compress, decompress};
+    use crate::io::Error;
+
+    fn roundtrip(data: &[u8]) -> usize {
+        let compressed = compress(data);
+        let mut decompressed = vec![0_u8; data.len()];
+        assert_eq!(Ok(data.len()), decompress(&compressed, &mut decompressed));
+        assert_eq!(data, &decompressed[..]);
+        compressed.len()
+    }
+
+    #[test]
+    fn test_lz77_roundtrip() {
+        assert_eq!(0, roundtrip(&[]));
+        assert_eq!(3, roundtrip(b"ab"));
+        // a run is a literal followed by an overlapping match
+        assert_eq!(2 + 3 * 8, roundtrip(&[7_u8; 1000]));
+
+        let text = b"It was the best of times, it was the worst of times, it was the age of wisdom";
+        assert!(roundtrip(text) < text.len());
+
+        let mut state = 0x1234_5678_u32;
+        let noise: Vec<u8> = (0..5000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect();
+        assert!(roundtrip(&noise) <= noise.len() + noise.len().div_ceil(128));
+    }
+
+    #[test]
+    fn test_lz77_malformed() {
+        let mut output = [0_u8; 8];
+        // literals past the end of the input
+        assert_eq!(Err(Error::DecodeError), decompress(&[3, 1, 2], &mut output));
+        // a match before the start of the output
+        assert_eq!(
+            Err(Error::DecodeError),
+            decompress(&[0, 1, 0x80, 2, 0], &mut output)
+        );
+        // a match without a distance
+        assert_eq!(
+            Err(Error::DecodeError),
+            decompress(&[0, 1, 0x80], &mut output)
+        );
+        // output that doesn't fit
+        assert_eq!(
+            Err(Error::DecodeError),
+            decompress(&compress(&[1_u8; 9]), &mut output)
+        );
+    }
+}
@@ -0,0 +1,3 @@
+pub use lz77::{compress, decompress};
+
+pub mod lz77;
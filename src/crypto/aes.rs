@@ -0,0 +1,261 @@
+use crate::io::{Error, Result};
+
+/// The size of an AES block in bytes.
+pub const BLOCK_SIZE: usize = 16;
+
+/// The maximum number of rounds, used with 256 bit keys.
+const MAX_ROUNDS: usize = 14;
+
+static SBOX: [u8; 256] = make_sbox();
+static INV_SBOX: [u8; 256] = make_inv_sbox();
+
+/// Multiplies two elements of GF(2^8) with the AES polynomial.
+const fn gmul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        a = xtime(a);
+        b >>= 1;
+    }
+    product
+}
+
+/// Multiplies an element of GF(2^8) by `x`.
+const fn xtime(a: u8) -> u8 {
+    (a << 1) ^ if a & 0x80 != 0 { 0x1B } else { 0 }
+}
+
+const fn make_sbox() -> [u8; 256] {
+    let mut sbox = [0_u8; 256];
+    let mut i = 0;
+    while i < 256 {
+        // the multiplicative inverse is x^254, and zero maps to zero
+        let mut inverse = 1_u8;
+        let mut e = 0;
+        while e < 254 {
+            inverse = gmul(inverse, i as u8);
+            e += 1;
+        }
+        if i == 0 {
+            inverse = 0;
+        }
+        sbox[i] = inverse
+            ^ inverse.rotate_left(1)
+            ^ inverse.rotate_left(2)
+            ^ inverse.rotate_left(3)
+            ^ inverse.rotate_left(4)
+            ^ 0x63;
+        i += 1;
+    }
+    sbox
+}
+
+const fn make_inv_sbox() -> [u8; 256] {
+    let sbox = make_sbox();
+    let mut inv = [0_u8; 256];
+    let mut i = 0;
+    while i < 256 {
+        inv[sbox[i] as usize] = i as u8;
+        i += 1;
+    }
+    inv
+}
+
+/// The AES block cipher (FIPS 197) with a 128, 192 or 256 bit key.
+///
+/// ```
+/// use kstd::crypto::Aes;
+///
+/// let aes = Aes::new(&[0_u8; 16]).unwrap();
+/// let mut block = *b"sixteen byte msg";
+/// aes.encrypt_block(&mut block);
+/// aes.decrypt_block(&mut block);
+/// assert_eq!(b"sixteen byte msg", &block);
+/// ```
+pub struct Aes {
+    round_keys: [[u8; BLOCK_SIZE]; MAX_ROUNDS + 1],
+    rounds: usize,
+}
+
+impl Aes {
+    /// Expands the given key, which must be 16, 24 or 32 bytes long,
+    /// otherwise this fails with [`Error::InvalidArgument`].
+    pub fn new(key: &[u8]) -> Result<Self> {
+        let nk = match key.len() {
+            16 | 24 | 32 => key.len() / 4,
+            _ => return Err(Error::InvalidArgument),
+        };
+        let rounds = nk + 6;
+
+        let mut words = [[0_u8; 4]; 4 * (MAX_ROUNDS + 1)];
+        for (word, chunk) in words.iter_mut().zip(key.as_chunks::<4>().0) {
+            *word = *chunk;
+        }
+        let mut rcon = 1_u8;
+        for i in nk..4 * (rounds + 1) {
+            let mut temp = words[i - 1];
+            if i % nk == 0 {
+                temp.rotate_left(1);
+                temp = temp.map(|b| SBOX[b as usize]);
+                temp[0] ^= rcon;
+                rcon = xtime(rcon);
+            } else if nk > 6 && i % nk == 4 {
+                temp = temp.map(|b| SBOX[b as usize]);
+            }
+            for j in 0..4 {
+                words[i][j] = words[i - nk][j] ^ temp[j];
+            }
+        }
+
+        let mut round_keys = [[0_u8; BLOCK_SIZE]; MAX_ROUNDS + 1];
+        for (round_key, chunk) in round_keys.iter_mut().zip(words.as_chunks::<4>().0) {
+            for (j, word) in chunk.iter().enumerate() {
+                round_key[4 * j..4 * j + 4].copy_from_slice(word);
+            }
+        }
+        words.fill([0; 4]);
+        Ok(Self { round_keys, rounds })
+    }
+
+    pub fn encrypt_block(&self, block: &mut [u8; BLOCK_SIZE]) {
+        add_round_key(block, &self.round_keys[0]);
+        for round_key in &self.round_keys[1..self.rounds] {
+            sub_bytes(block, &SBOX);
+            shift_rows(block);
+            mix_columns(block);
+            add_round_key(block, round_key);
+        }
+        sub_bytes(block, &SBOX);
+        shift_rows(block);
+        add_round_key(block, &self.round_keys[self.rounds]);
+    }
+
+    pub fn decrypt_block(&self, block: &mut [u8; BLOCK_SIZE]) {
+        add_round_key(block, &self.round_keys[self.rounds]);
+        for round_key in self.round_keys[1..self.rounds].iter().rev() {
+            inv_shift_rows(block);
+            sub_bytes(block, &INV_SBOX);
+            add_round_key(block, round_key);
+            inv_mix_columns(block);
+        }
+        inv_shift_rows(block);
+        sub_bytes(block, &INV_SBOX);
+        add_round_key(block, &self.round_keys[0]);
+    }
+}
+
+impl Drop for Aes {
+    fn drop(&mut self) {
+        // volatile, so that clearing the key schedule isn't optimized away
+        for byte in self.round_keys.as_flattened_mut() {
+            // SAFETY: the pointer comes from a mutable reference, so it is valid and aligned
+            unsafe { core::ptr::write_volatile(byte, 0) };
+        }
+    }
+}
+
+fn add_round_key(block: &mut [u8; BLOCK_SIZE], round_key: &[u8; BLOCK_SIZE]) {
+    for (b, k) in block.iter_mut().zip(round_key) {
+        *b ^= k;
+    }
+}
+
+fn sub_bytes(block: &mut [u8; BLOCK_SIZE], sbox: &[u8; 256]) {
+    for b in block.iter_mut() {
+        *b = sbox[*b as usize];
+    }
+}
+
+// The block is stored column by column, so row `r` consists of
+// the bytes `r`, `r + 4`, `r + 8` and `r + 12`.
+
+fn shift_rows(block: &mut [u8; BLOCK_SIZE]) {
+    let old = *block;
+    for c in 0..4 {
+        for r in 0..4 {
+            block[4 * c + r] = old[4 * ((c + r) % 4) + r];
+        }
+    }
+}
+
+fn inv_shift_rows(block: &mut [u8; BLOCK_SIZE]) {
+    let old = *block;
+    for c in 0..4 {
+        for r in 0..4 {
+            block[4 * ((c + r) % 4) + r] = old[4 * c + r];
+        }
+    }
+}
+
+fn mix_columns(block: &mut [u8; BLOCK_SIZE]) {
+    for column in block.as_chunks_mut::<4>().0 {
+        let [a0, a1, a2, a3] = *column;
+        column[0] = xtime(a0) ^ xtime(a1) ^ a1 ^ a2 ^ a3;
+        column[1] = a0 ^ xtime(a1) ^ xtime(a2) ^ a2 ^ a3;
+        column[2] = a0 ^ a1 ^ xtime(a2) ^ xtime(a3) ^ a3;
+        column[3] = xtime(a0) ^ a0 ^ a1 ^ a2 ^ xtime(a3);
+    }
+}
+
+fn inv_mix_columns(block: &mut [u8; BLOCK_SIZE]) {
+    for column in block.as_chunks_mut::<4>().0 {
+        let [a0, a1, a2, a3] = *column;
+        column[0] = gmul(a0, 14) ^ gmul(a1, 11) ^ gmul(a2, 13) ^ gmul(a3, 9);
+        column[1] = gmul(a0, 9) ^ gmul(a1, 14) ^ gmul(a2, 11) ^ gmul(a3, 13);
+        column[2] = gmul(a0, 13) ^ gmul(a1, 9) ^ gmul(a2, 14) ^ gmul(a3, 11);
+        column[3] = gmul(a0, 11) ^ gmul(a1, 13) ^ gmul(a2, 9) ^ gmul(a3, 14);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crypto::aes::{Aes, SBOX};
+    use crate::crypto::hex;
+    use crate::io::Error;
+
+    #[test]
+    fn test_aes_sbox() {
+        assert_eq!(0x63, SBOX[0x00]);
+        assert_eq!(0x7C, SBOX[0x01]);
+        assert_eq!(0xED, SBOX[0x53]);
+        assert_eq!(0x16, SBOX[0xFF]);
+    }
+
+    #[test]
+    fn test_aes_known_answers() {
+        // FIPS 197, appendix C
+        let plaintext: [u8; 16] = hex("00112233445566778899aabbccddeeff");
+        let cases = [
+            (
+                &hex::<32>("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f")
+                    [..16],
+                hex::<16>("69c4e0d86a7b0430d8cdb78070b4c55a"),
+            ),
+            (
+                &hex::<32>("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f")
+                    [..24],
+                hex::<16>("dda97ca4864cdfe06eaf70a0ec0d7191"),
+            ),
+            (
+                &hex::<32>("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f")[..],
+                hex::<16>("8ea2b7ca516745bfeafc49904b496089"),
+            ),
+        ];
+        for (key, ciphertext) in cases {
+            let aes = Aes::new(key).unwrap();
+            let mut block = plaintext;
+            aes.encrypt_block(&mut block);
+            assert_eq!(ciphertext, block);
+            aes.decrypt_block(&mut block);
+            assert_eq!(plaintext, block);
+        }
+    }
+
+    #[test]
+    fn test_aes_invalid_key() {
+        assert!(matches!(Aes::new(&[0_u8; 15]), Err(Error::InvalidArgument)));
+        assert!(matches!(Aes::new(&[0_u8; 64]), Err(Error::InvalidArgument)));
+    }
+}
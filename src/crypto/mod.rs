@@ -0,0 +1,22 @@
+//! Pure Rust implementations of ciphers for storage encryption.
+//!
+//! These implementations use lookup tables and are not hardened against
+//! timing side channels. They are meant for protecting data at rest, where
+//! an attacker has access to the storage but can't observe the machine
+//! while it encrypts.
+
+pub use aes::Aes;
+pub use xts::Xts;
+
+pub mod aes;
+pub mod xts;
+
+/// Decodes a hex string, for writing test vectors as they are published.
+#[cfg(test)]
+pub(crate) fn hex<const N: usize>(s: &str) -> [u8; N] {
+    let mut bytes = [0_u8; N];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&s[2 * i..2 * i + 2], 16).unwrap();
+    }
+    bytes
+}
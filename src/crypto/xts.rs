@@ -0,0 +1,142 @@
+use crate::crypto::aes::{Aes, BLOCK_SIZE};
+use crate::io::{Error, Result};
+
+/// The XTS mode of AES (IEEE 1619), which encrypts every sector of a storage
+/// device with a tweak derived from its sector number.
+///
+/// Identical plaintext in different sectors results in different ciphertext,
+/// and the ciphertext has the same size as the plaintext, so no space is
+/// needed for an initialization vector. Sectors must be a multiple of the
+/// AES block size, ciphertext stealing for other sizes is not supported.
+pub struct Xts {
+    data: Aes,
+    tweak: Aes,
+}
+
+impl Xts {
+    /// Creates the cipher from the given key, which consists of the data key
+    /// followed by the tweak key. The key must be 32 bytes long for AES-128,
+    /// or 64 bytes long for AES-256, otherwise this fails with
+    /// [`Error::InvalidArgument`].
+    pub fn new(key: &[u8]) -> Result<Self> {
+        if key.len() != 32 && key.len() != 64 {
+            return Err(Error::InvalidArgument);
+        }
+        let (data, tweak) = key.split_at(key.len() / 2);
+        Ok(Self {
+            data: Aes::new(data)?,
+            tweak: Aes::new(tweak)?,
+        })
+    }
+
+    /// Encrypts the given sector in place. Fails with [`Error::InvalidArgument`]
+    /// if its length is not a non-zero multiple of the AES block size.
+    pub fn encrypt_sector(&self, sector: u64, data: &mut [u8]) -> Result<()> {
+        self.process(sector, data, |aes, block| aes.encrypt_block(block))
+    }
+
+    /// Decrypts the given sector in place. Fails with [`Error::InvalidArgument`]
+    /// if its length is not a non-zero multiple of the AES block size.
+    pub fn decrypt_sector(&self, sector: u64, data: &mut [u8]) -> Result<()> {
+        self.process(sector, data, |aes, block| aes.decrypt_block(block))
+    }
+
+    fn process(
+        &self,
+        sector: u64,
+        data: &mut [u8],
+        cipher: impl Fn(&Aes, &mut [u8; BLOCK_SIZE]),
+    ) -> Result<()> {
+        if data.is_empty() || !data.len().is_multiple_of(BLOCK_SIZE) {
+            return Err(Error::InvalidArgument);
+        }
+        let mut tweak = [0_u8; BLOCK_SIZE];
+        tweak[..8].copy_from_slice(&sector.to_le_bytes());
+        self.tweak.encrypt_block(&mut tweak);
+
+        for block in data.as_chunks_mut::<BLOCK_SIZE>().0 {
+            xor(block, &tweak);
+            cipher(&self.data, block);
+            xor(block, &tweak);
+            multiply_by_alpha(&mut tweak);
+        }
+        Ok(())
+    }
+}
+
+fn xor(block: &mut [u8; BLOCK_SIZE], tweak: &[u8; BLOCK_SIZE]) {
+    for (b, t) in block.iter_mut().zip(tweak) {
+        *b ^= t;
+    }
+}
+
+/// Multiplies the tweak by the primitive element of GF(2^128), which is
+/// stored in little endian order.
+fn multiply_by_alpha(tweak: &mut [u8; BLOCK_SIZE]) {
+    let mut carry = 0;
+    for b in tweak.iter_mut() {
+        let next = *b >> 7;
+        *b = (*b << 1) | carry;
+        carry = next;
+    }
+    if carry != 0 {
+        tweak[0] ^= 0x87;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crypto::{hex, Xts};
+    use crate::io::Error;
+
+    /// Checks a test vector from IEEE 1619, appendix B.
+    fn check(key: &[u8], sector: u64, plaintext: &[u8], ciphertext: &[u8]) {
+        let xts = Xts::new(key).unwrap();
+        let mut data = plaintext.to_vec();
+        xts.encrypt_sector(sector, &mut data).unwrap();
+        assert_eq!(ciphertext, &data[..]);
+        xts.decrypt_sector(sector, &mut data).unwrap();
+        assert_eq!(plaintext, &data[..]);
+    }
+
+    #[test]
+    fn test_xts_known_answers() {
+        check(
+            &[0_u8; 32],
+            0,
+            &[0_u8; 32],
+            &hex::<32>("917cf69ebd68b2ec9b9fe9a3eadda692cd43d2f59598ed858c02c2652fbf922e"),
+        );
+        let mut key = [0x11_u8; 32];
+        key[16..].fill(0x22);
+        check(
+            &key,
+            0x33_3333_3333,
+            &[0x44_u8; 32],
+            &hex::<32>("c454185e6a16936e39334038acef838bfb186fff7480adc4289382ecd6d394f0"),
+        );
+    }
+
+    #[test]
+    fn test_xts_sectors_differ() {
+        let xts = Xts::new(&[7_u8; 64]).unwrap();
+        let mut first = [0_u8; 512];
+        let mut second = [0_u8; 512];
+        xts.encrypt_sector(1, &mut first).unwrap();
+        xts.encrypt_sector(2, &mut second).unwrap();
+        assert_ne!(first, second);
+        // every AES block of a sector uses a different tweak
+        assert_ne!(first[..16], first[16..32]);
+    }
+
+    #[test]
+    fn test_xts_invalid() {
+        assert!(matches!(Xts::new(&[0_u8; 16]), Err(Error::InvalidArgument)));
+        let xts = Xts::new(&[0_u8; 32]).unwrap();
+        assert_eq!(
+            Err(Error::InvalidArgument),
+            xts.encrypt_sector(0, &mut [0_u8; 24])
+        );
+        assert_eq!(Err(Error::InvalidArgument), xts.encrypt_sector(0, &mut []));
+    }
+}
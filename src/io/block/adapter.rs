@@ -0,0 +1,270 @@
+use alloc::vec;
+
+use crate::io::block::BlockDevice;
+use crate::io::{Error, Result};
+
+/// Exposes a [`BlockDevice`] with a different block size than the inner device.
+///
+/// One of the two block sizes must be a multiple of the other. If the logical
+/// block size is larger than the one of the inner device, every logical block
+/// spans multiple consecutive inner blocks. If it is smaller, multiple logical
+/// blocks share one inner block, and writes that don't cover whole inner blocks
+/// are done with read-modify-write.
+///
+/// If the logical block size is larger, trailing inner blocks that don't make
+/// up a full logical block are not accessible.
+pub struct BlockSizeAdapter<D>
+where
+    D: BlockDevice,
+{
+    inner: D,
+    block_size: usize,
+    inner_block_size: usize,
+}
+
+impl<D> BlockSizeAdapter<D>
+where
+    D: BlockDevice,
+{
+    /// Creates an adapter with the given logical block size over the given device.
+    /// Fails with [`Error::InvalidArgument`] if neither block size is a multiple
+    /// of the other.
+    pub fn new(inner: D, block_size: usize) -> Result<Self> {
+        let inner_block_size = inner.block_size();
+        if block_size == 0
+            || inner_block_size == 0
+            || !(block_size.is_multiple_of(inner_block_size)
+                || inner_block_size.is_multiple_of(block_size))
+        {
+            return Err(Error::InvalidArgument);
+        }
+        Ok(Self {
+            inner,
+            block_size,
+            inner_block_size,
+        })
+    }
+
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    pub const fn get_ref(&self) -> &D {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut D {
+        &mut self.inner
+    }
+
+    /// Checks that the given number of blocks, starting at the given block, exist,
+    /// and that a buffer of the given length can hold them.
+    fn check(&self, block: u64, count: usize, len: usize) -> Result<()> {
+        if len < count * self.block_size {
+            return Err(Error::BufferTooSmall);
+        }
+        match block.checked_add(count as u64) {
+            Some(end) if block < self.block_count() as u64 && end <= self.block_count() as u64 => {
+                Ok(())
+            }
+            _ => Err(Error::NoSuchBlock),
+        }
+    }
+
+    /// The number of inner blocks per logical block. Only meaningful if the
+    /// logical block size is larger than the inner one.
+    fn inner_blocks_per_block(&self) -> usize {
+        self.block_size / self.inner_block_size
+    }
+
+    /// Returns the inner block that contains the given logical block, and the
+    /// offset of the logical block within it. Only meaningful if the logical
+    /// block size is smaller than the inner one.
+    fn locate(&self, block: u64) -> (u64, usize) {
+        let per_block = (self.inner_block_size / self.block_size) as u64;
+        (
+            block / per_block,
+            (block % per_block) as usize * self.block_size,
+        )
+    }
+}
+
+impl<D> BlockDevice for BlockSizeAdapter<D>
+where
+    D: BlockDevice,
+{
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn block_count(&self) -> usize {
+        if self.block_size >= self.inner_block_size {
+            self.inner.block_count() / (self.block_size / self.inner_block_size)
+        } else {
+            self.inner.block_count() * (self.inner_block_size / self.block_size)
+        }
+    }
+
+    fn read_block(&self, block: u64, buf: &mut dyn AsMut<[u8]>) -> Result<usize> {
+        self.read_blocks(block, 1, buf)
+    }
+
+    fn write_block(&mut self, block: u64, buf: &dyn AsRef<[u8]>) -> Result<usize> {
+        self.write_blocks(block, 1, buf)
+    }
+
+    fn read_blocks(&self, start: u64, count: usize, buf: &mut dyn AsMut<[u8]>) -> Result<usize> {
+        let buffer = buf.as_mut();
+        self.check(start, count, buffer.len())?;
+        let len = count * self.block_size;
+
+        if self.block_size >= self.inner_block_size {
+            let per_block = self.inner_blocks_per_block();
+            self.inner.read_blocks(
+                start * per_block as u64,
+                count * per_block,
+                &mut &mut buffer[..len],
+            )?;
+        } else {
+            // every inner block is read once, no matter how many of its logical
+            // blocks are requested
+            let mut data = vec![0_u8; self.inner_block_size];
+            let mut pos = 0;
+            while pos < len {
+                let (inner_block, offset) = self.locate(start + (pos / self.block_size) as u64);
+                let n = (self.inner_block_size - offset).min(len - pos);
+                self.inner.read_block(inner_block, &mut data)?;
+                buffer[pos..pos + n].copy_from_slice(&data[offset..offset + n]);
+                pos += n;
+            }
+        }
+        Ok(len)
+    }
+
+    fn write_blocks(&mut self, start: u64, count: usize, buf: &dyn AsRef<[u8]>) -> Result<usize> {
+        let buffer = buf.as_ref();
+        self.check(start, count, buffer.len())?;
+        let len = count * self.block_size;
+
+        if self.block_size >= self.inner_block_size {
+            let per_block = self.inner_blocks_per_block();
+            self.inner.write_blocks(
+                start * per_block as u64,
+                count * per_block,
+                &&buffer[..len],
+            )?;
+        } else {
+            // inner blocks that are completely overwritten are written directly,
+            // only partially overwritten ones need a read-modify-write
+            let mut data = vec![0_u8; self.inner_block_size];
+            let mut pos = 0;
+            while pos < len {
+                let (inner_block, offset) = self.locate(start + (pos / self.block_size) as u64);
+                let n = (self.inner_block_size - offset).min(len - pos);
+                if n == self.inner_block_size {
+                    self.inner
+                        .write_block(inner_block, &&buffer[pos..pos + n])?;
+                } else {
+                    self.inner.read_block(inner_block, &mut data)?;
+                    data[offset..offset + n].copy_from_slice(&buffer[pos..pos + n]);
+                    self.inner.write_block(inner_block, &data)?;
+                }
+                pos += n;
+            }
+        }
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use crate::io::block::adapter::BlockSizeAdapter;
+    use crate::io::block::faulty::FaultyDevice;
+    use crate::io::block::memory::MemoryBlockDevice;
+    use crate::io::block::BlockDevice;
+    use crate::io::Error;
+
+    fn device(block_size: usize, len: usize) -> MemoryBlockDevice {
+        let image: Vec<u8> = (0..len).map(|i| (i / 4) as u8).collect();
+        MemoryBlockDevice::from_image(image, block_size)
+    }
+
+    #[test]
+    fn test_adapter_invalid_block_size() {
+        assert!(matches!(
+            BlockSizeAdapter::new(device(512, 4096), 768),
+            Err(Error::InvalidArgument)
+        ));
+        assert!(matches!(
+            BlockSizeAdapter::new(device(512, 4096), 0),
+            Err(Error::InvalidArgument)
+        ));
+    }
+
+    #[test]
+    fn test_adapter_larger_blocks() {
+        // 10 inner blocks only make up 2 full logical blocks
+        let mut adapter = BlockSizeAdapter::new(device(4, 40), 16).unwrap();
+        assert_eq!(16, adapter.block_size());
+        assert_eq!(2, adapter.block_count());
+
+        let mut data = [0_u8; 16];
+        adapter.read_block(1, &mut data).unwrap();
+        assert_eq!([4, 4, 4, 4, 5, 5, 5, 5, 6, 6, 6, 6, 7, 7, 7, 7], data);
+        assert_eq!(Err(Error::NoSuchBlock), adapter.read_block(2, &mut data));
+
+        adapter.write_block(0, &[9_u8; 16]).unwrap();
+        let mut data = [0_u8; 32];
+        adapter.read_blocks(0, 2, &mut data).unwrap();
+        assert_eq!([9_u8; 16], data[..16]);
+        assert_eq!([4_u8; 4], data[16..20]);
+        assert_eq!(
+            Err(Error::NoSuchBlock),
+            adapter.read_blocks(1, 2, &mut [0_u8; 32])
+        );
+    }
+
+    #[test]
+    fn test_adapter_smaller_blocks() {
+        let mut adapter = BlockSizeAdapter::new(device(16, 32), 4).unwrap();
+        assert_eq!(8, adapter.block_count());
+
+        let mut data = [0_u8; 4];
+        adapter.read_block(5, &mut data).unwrap();
+        assert_eq!([5_u8; 4], data);
+        assert_eq!(Err(Error::NoSuchBlock), adapter.read_block(8, &mut data));
+
+        adapter.write_block(6, &[9_u8; 4]).unwrap();
+        adapter.read_block(6, &mut data).unwrap();
+        assert_eq!([9_u8; 4], data);
+        let image = adapter.into_inner().into_inner();
+        assert_eq!(
+            [4, 4, 4, 4, 5, 5, 5, 5, 9, 9, 9, 9, 7, 7, 7, 7],
+            image[16..]
+        );
+    }
+
+    #[test]
+    fn test_adapter_smaller_blocks_multiple() {
+        let mut adapter = BlockSizeAdapter::new(FaultyDevice::new(device(16, 64)), 4).unwrap();
+
+        // logical blocks 1..9 span the inner blocks 0..3, which are read once each
+        let mut data = [0_u8; 32];
+        adapter.read_blocks(1, 8, &mut data).unwrap();
+        assert_eq!([1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4], data[..16]);
+        assert_eq!([8_u8; 4], data[28..]);
+        assert_eq!(3, adapter.get_ref().reads());
+
+        // inner block 1 is overwritten completely, so it is not read
+        adapter.write_blocks(2, 8, &[9_u8; 32]).unwrap();
+        assert_eq!(5, adapter.get_ref().reads());
+        assert_eq!(3, adapter.get_ref().writes());
+        let mut data = [0_u8; 48];
+        adapter.read_blocks(0, 12, &mut data).unwrap();
+        assert_eq!([0, 0, 0, 0, 1, 1, 1, 1], data[..8]);
+        assert_eq!([9_u8; 32], data[8..40]);
+        assert_eq!([10, 10, 10, 10, 11, 11, 11, 11], data[40..]);
+    }
+}
@@ -1,57 +1,474 @@
-use alloc::rc::Rc;
+use alloc::collections::btree_map::Entry;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
 use alloc::vec;
 use alloc::vec::Vec;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 
 use spin::{Mutex, RwLock};
 
-use crate::collections::lru::LruCache;
+use crate::collections::{CachePolicy, CacheStats, LruMap};
 use crate::io::block::BlockDevice;
 use crate::io::{Error, Result};
 
-struct CacheBlock<D>
-where
-    D: BlockDevice,
-{
-    device: Rc<RwLock<D>>,
+/// A block held by a [`BlockCache`]. The contents are only accessible
+/// through the cache.
+pub struct CacheBlock {
     num: u64,
     data: Vec<u8>,
+    /// Whether the data has been modified since it was last
+    /// read from or written to the device.
+    dirty: bool,
+    /// Whether the block was loaded by readahead, and has not been read since.
+    prefetched: bool,
 }
 
-impl<D> Drop for CacheBlock<D>
-where
-    D: BlockDevice,
-{
-    fn drop(&mut self) {
-        let _ = self.device.write().write_block(self.num, &self.data);
-        // don't panic, even if the write fails
-    }
+/// The values that a [`BlockCache`] stores in its [`CachePolicy`].
+pub type CacheEntry = Arc<RwLock<CacheBlock>>;
+
+/// A block that is in use by a guard or a write, and must therefore be kept
+/// even if the policy evicts it.
+struct Pinned {
+    entry: CacheEntry,
+    /// The number of users. A block with no users is only kept if it was evicted
+    /// while pinned, and writing it back afterwards failed.
+    count: usize,
+}
+
+/// Statistics about the readahead of a [`BlockCache`].
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct ReadaheadStats {
+    /// The number of batched device reads that were issued because
+    /// of a sequential access pattern.
+    pub batches: usize,
+    /// The number of blocks that were loaded in addition to the
+    /// requested ones.
+    pub prefetched: usize,
+    /// The number of reads that were served from a prefetched block.
+    pub hits: usize,
 }
 
-pub struct BlockCache<D>
+/// A write-back cache for a [`BlockDevice`].
+///
+/// Reads are served from the cache if possible. Writes only modify the
+/// cached block and mark it as dirty. Dirty blocks are written to the device
+/// when they are evicted from the cache, or when [`BlockCache::sync`] is called.
+/// Clean blocks are never written back.
+///
+/// The cache is [`Send`] and [`Sync`], and [`BlockDevice`] is also implemented
+/// for `&BlockCache`, so that a single cache can be shared and written to from
+/// multiple CPUs.
+///
+/// Instead of copying a block, it can also be borrowed in place with
+/// [`BlockCache::get_block`] and [`BlockCache::get_block_mut`].
+///
+/// If readahead is enabled with [`BlockCache::set_readahead`], a miss in a
+/// sequential scan loads the following blocks with the same device read.
+///
+/// Which blocks are evicted is decided by the [`CachePolicy`] `P`. [`BlockCache::new`]
+/// uses an [`LruMap`], and [`BlockCache::with_policy`] accepts any other policy,
+/// like the scan resistant [`ArcMap`](crate::collections::ArcMap) for workloads
+/// that mix repeated metadata accesses with large sequential reads.
+///
+/// When the cache is dropped, all dirty blocks are written back, but errors
+/// are ignored. Call [`BlockCache::sync`] before dropping the cache if you
+/// need to know whether all data made it to the device.
+pub struct BlockCache<D, P = LruMap<u64, CacheEntry>>
 where
-    D: BlockDevice,
+    D: BlockDevice + Send + Sync,
+    P: CachePolicy<u64, CacheEntry>,
 {
-    cache: Mutex<LruCache<Rc<RwLock<CacheBlock<D>>>>>,
+    cache: Mutex<P>,
+    /// Blocks that must not be dropped, even if they are evicted. Only modified
+    /// while holding the lock of `cache`.
+    pinned: Mutex<BTreeMap<u64, Pinned>>,
     block_size: usize,
-    device: Rc<RwLock<D>>,
+    device: Arc<RwLock<D>>,
+    readahead: AtomicUsize,
+    /// The block that was read last, used to detect sequential reads.
+    last_read: AtomicU64,
+    readahead_batches: AtomicUsize,
+    readahead_prefetched: AtomicUsize,
+    readahead_hits: AtomicUsize,
+    writebacks: AtomicUsize,
 }
 
 impl<D> BlockCache<D>
 where
-    D: BlockDevice,
+    D: BlockDevice + Send + Sync,
 {
+    /// Creates a cache that holds up to `size` blocks, and evicts
+    /// the least recently used one.
     pub fn new(device: D, size: usize) -> Self {
+        Self::with_policy(device, LruMap::new(size))
+    }
+}
+
+impl<D, P> BlockCache<D, P>
+where
+    D: BlockDevice + Send + Sync,
+    P: CachePolicy<u64, CacheEntry>,
+{
+    /// Creates a cache that evicts blocks as decided by the given policy.
+    /// The capacity of the policy is the number of blocks in the cache.
+    pub fn with_policy(device: D, policy: P) -> Self {
         Self {
-            cache: Mutex::new(LruCache::new(size)),
+            cache: Mutex::new(policy),
+            pinned: Mutex::new(BTreeMap::new()),
             block_size: device.block_size(),
-            device: Rc::new(RwLock::new(device)),
+            device: Arc::new(RwLock::new(device)),
+            readahead: AtomicUsize::new(0),
+            last_read: AtomicU64::new(u64::MAX),
+            readahead_batches: AtomicUsize::default(),
+            readahead_prefetched: AtomicUsize::default(),
+            readahead_hits: AtomicUsize::default(),
+            writebacks: AtomicUsize::default(),
+        }
+    }
+
+    /// Borrows the given block from this cache without copying it, loading it
+    /// from the device if necessary.
+    ///
+    /// The block is pinned while the guard is held, meaning that it stays in
+    /// memory even if it is evicted. Writes to the block, including ones through
+    /// [`BlockCache::get_block_mut`], wait until the guard is dropped. Doing so
+    /// on the CPU that holds the guard never returns. [`BlockCache::sync`] doesn't
+    /// wait, but the block remains dirty until it is synced after the guard is dropped.
+    pub fn get_block(&self, block: u64) -> Result<BlockRef<'_, D, P>> {
+        let entry = self.acquire(block)?;
+        // the guard is released by BlockRef::drop
+        core::mem::forget(entry.read());
+        Ok(BlockRef {
+            cache: self,
+            num: block,
+            entry,
+        })
+    }
+
+    /// Mutably borrows the given block from this cache without copying it,
+    /// loading it from the device if necessary. The block is marked as dirty
+    /// as soon as it is accessed mutably.
+    ///
+    /// The block is pinned while the guard is held, meaning that it stays in
+    /// memory even if it is evicted. All other accesses to the block, as well
+    /// as [`BlockCache::sync`], [`BlockCache::dirty_count`] and [`BlockCache::stats`],
+    /// wait until the guard is dropped. Calling them on the CPU that holds the
+    /// guard never returns.
+    pub fn get_block_mut(&self, block: u64) -> Result<BlockRefMut<'_, D, P>> {
+        let entry = self.acquire(block)?;
+        // the guard is released by BlockRefMut::drop
+        core::mem::forget(entry.write());
+        Ok(BlockRefMut {
+            cache: self,
+            num: block,
+            entry,
+        })
+    }
+
+    /// Sets the number of blocks that are loaded in addition to the requested
+    /// one, if a read misses the cache and directly follows a read of the
+    /// previous block. The number is capped so that a batch fits into the cache.
+    /// `0` disables readahead, which is the default.
+    pub fn set_readahead(&self, blocks: usize) {
+        self.readahead.store(blocks, Ordering::Relaxed);
+    }
+
+    pub fn readahead(&self) -> usize {
+        self.readahead.load(Ordering::Relaxed)
+    }
+
+    pub fn readahead_stats(&self) -> ReadaheadStats {
+        ReadaheadStats {
+            batches: self.readahead_batches.load(Ordering::Relaxed),
+            prefetched: self.readahead_prefetched.load(Ordering::Relaxed),
+            hits: self.readahead_hits.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Returns statistics about this cache since it was created, or since the
+    /// last call to [`BlockCache::reset_stats`]. Reads and writes count as
+    /// lookups, blocks loaded by readahead don't.
+    pub fn stats(&self) -> CacheStats {
+        let stats = self.cache.lock().stats();
+        CacheStats {
+            writebacks: self.writebacks.load(Ordering::Relaxed),
+            dirty: self.dirty_count(),
+            ..stats
+        }
+    }
+
+    /// Resets the counters of [`BlockCache::stats`] and [`BlockCache::readahead_stats`].
+    /// The number of dirty and cached blocks is not affected.
+    pub fn reset_stats(&self) {
+        self.cache.lock().reset_stats();
+        self.writebacks.store(0, Ordering::Relaxed);
+        self.readahead_batches.store(0, Ordering::Relaxed);
+        self.readahead_prefetched.store(0, Ordering::Relaxed);
+        self.readahead_hits.store(0, Ordering::Relaxed);
+    }
+
+    /// Writes all dirty blocks in this cache to the device.
+    /// The blocks stay in the cache, but are no longer dirty.
+    /// If a write fails, the error is returned and the failed
+    /// block, as well as all blocks that were not yet written,
+    /// remain dirty.
+    ///
+    /// Blocks that are pinned by a guard are written as well, but remain
+    /// dirty, since a [`BlockRef`] may hold on to them until it is dropped.
+    pub fn sync(&self) -> Result<()> {
+        for block in self.blocks() {
+            let num = {
+                let block = block.read();
+                if !block.dirty {
+                    continue;
+                }
+                block.num
+            };
+            if self.is_pinned(num) {
+                // clearing the dirty flag needs the write lock, which would
+                // wait for the guards that hold the read lock
+                let block = block.read();
+                if block.dirty {
+                    self.write_data(&block)?;
+                }
+            } else {
+                self.write_back(&mut block.write())?;
+            }
+        }
+
+        // evicted blocks that could not be written back are no longer needed
+        let cache = self.cache.lock();
+        self.pinned
+            .lock()
+            .retain(|num, p| p.count > 0 || cache.contains_key(num) || p.entry.read().dirty);
+        Ok(())
+    }
+
+    /// Returns the number of blocks in this cache that have been
+    /// modified, but not yet written to the device.
+    pub fn dirty_count(&self) -> usize {
+        self.blocks().iter().filter(|b| b.read().dirty).count()
+    }
+
+    /// Returns all blocks that are held by this cache, including pinned
+    /// blocks that were evicted. The blocks are collected under the cache
+    /// lock, but not locked themselves, so that the caller can wait for
+    /// them without blocking the whole cache.
+    fn blocks(&self) -> Vec<CacheEntry> {
+        let cache = self.cache.lock();
+        let pinned = self.pinned.lock();
+        cache
+            .iter()
+            .map(|(_, b)| b.clone())
+            .chain(
+                pinned
+                    .iter()
+                    .filter(|(num, _)| !cache.contains_key(num))
+                    .map(|(_, p)| p.entry.clone()),
+            )
+            .collect()
+    }
+
+    fn write_back(&self, block: &mut CacheBlock) -> Result<()> {
+        if block.dirty {
+            self.write_data(block)?;
+            block.dirty = false;
+        }
+        Ok(())
+    }
+
+    /// Writes the given block to the device, without clearing its dirty flag.
+    fn write_data(&self, block: &CacheBlock) -> Result<()> {
+        let _ = self.device.write().write_block(block.num, &block.data)?;
+        self.writebacks.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Whether the given block is currently held by a guard.
+    fn is_pinned(&self, num: u64) -> bool {
+        self.pinned.lock().get(&num).is_some_and(|p| p.count > 0)
+    }
+
+    /// Counts a read of the given block as a readahead hit, if it was prefetched.
+    /// Must not be called while holding the cache lock, since the block may be
+    /// borrowed by a guard.
+    fn touch(&self, block: &CacheEntry) {
+        if block.read().prefetched {
+            let mut block = block.write();
+            if block.prefetched {
+                block.prefetched = false;
+                self.readahead_hits.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Inserts the given block into the locked cache. The caller must hold the
+    /// cache lock from the lookup until the insertion, otherwise another CPU
+    /// could insert the same block in between.
+    fn insert(&self, cache: &mut P, num: u64, block: CacheEntry) -> Result<()> {
+        if let Some((victim_num, victim)) = cache.victim(&num) {
+            // a pinned block may be borrowed, so we can't wait for its lock here,
+            // but it is kept until it's unpinned, and written back then
+            if !self.pinned.lock().contains_key(victim_num) {
+                // write back the block that is about to be evicted first, so that
+                // we don't lose its data if the write fails
+                self.write_back(&mut victim.write())?;
+            }
+        }
+        let _ = cache.insert(num, block);
+        Ok(())
+    }
+
+    /// Looks up the given block in the locked cache, including pinned blocks
+    /// that were evicted, and loads it from the device if it isn't there.
+    fn lookup(&self, cache: &mut P, block: u64, sequential: bool) -> Result<CacheEntry> {
+        if let Some(b) = cache.get(&block) {
+            return Ok(b.clone());
+        }
+
+        match self.restore_pinned(cache, block)? {
+            Some(b) => Ok(b),
+            // keep the cache locked while loading, so that no other CPU can
+            // write back a newer version of this block in the meantime
+            None => self.load(cache, block, sequential),
+        }
+    }
+
+    /// Inserts the given block back into the cache if it was evicted while
+    /// pinned, and returns it. Returns `None` if the block isn't pinned. Unlike
+    /// [`BlockCache::lookup`], this doesn't count as a lookup in the stats.
+    fn restore_pinned(&self, cache: &mut P, block: u64) -> Result<Option<CacheEntry>> {
+        let Some(b) = self.pinned.lock().get(&block).map(|p| p.entry.clone()) else {
+            return Ok(None);
+        };
+        // the evicted block is newer than the device, and in use again
+        self.insert(cache, block, b.clone())?;
+        if let Entry::Occupied(p) = self.pinned.lock().entry(block) {
+            if p.get().count == 0 {
+                let _ = p.remove();
+            }
+        }
+        Ok(Some(b))
+    }
+
+    /// Looks up the given block and pins it, see [`BlockCache::pin`].
+    fn acquire(&self, block: u64) -> Result<CacheEntry> {
+        let entry = {
+            let mut cache = self.cache.lock();
+            let entry = self.lookup(&mut cache, block, false)?;
+            self.pin(block, &entry);
+            entry
+        };
+        self.touch(&entry);
+        Ok(entry)
+    }
+
+    /// Pins the given block, which must be the current version of the block
+    /// number. Must be called while holding the cache lock.
+    fn pin(&self, num: u64, block: &CacheEntry) {
+        self.pinned
+            .lock()
+            .entry(num)
+            .or_insert_with(|| Pinned {
+                entry: block.clone(),
+                count: 0,
+            })
+            .count += 1;
+    }
+
+    /// Releases a pin of the given block. If the block was evicted while it was
+    /// pinned, and this was the last pin, the block is written back. If that fails,
+    /// the block is kept until the next successful [`BlockCache::sync`].
+    fn release(&self, block: u64) -> Result<()> {
+        let cache = self.cache.lock();
+        let mut pinned = self.pinned.lock();
+        let Entry::Occupied(mut p) = pinned.entry(block) else {
+            return Ok(());
+        };
+        p.get_mut().count -= 1;
+        if p.get().count > 0 {
+            return Ok(());
+        }
+        if cache.contains_key(&block) {
+            let _ = p.remove();
+            return Ok(());
+        }
+        // nobody else can find the block anymore, so this doesn't have to wait
+        self.write_back(&mut p.get().entry.write())?;
+        let _ = p.remove();
+        Ok(())
+    }
+
+    /// Loads the given block from the device into the locked cache. If `sequential`
+    /// is set and readahead is enabled, the following blocks are loaded with the
+    /// same device read. Blocks that are already cached are not replaced.
+    fn load(&self, cache: &mut P, block: u64, sequential: bool) -> Result<CacheEntry> {
+        let readahead = if sequential {
+            let remaining = (self.device.read().block_count() as u64).saturating_sub(block + 1);
+            self.readahead()
+                .min(cache.capacity().saturating_sub(1))
+                .min(remaining as usize)
+        } else {
+            0
+        };
+        let count = 1 + readahead;
+
+        let mut data = vec![0_u8; count * self.block_size];
+        if count == 1 {
+            let _ = self.device.read().read_block(block, &mut data)?;
+        } else {
+            let _ = self.device.read().read_blocks(block, count, &mut data)?;
         }
+        let mut chunks = data.chunks_exact(self.block_size);
+
+        let b = Arc::new(RwLock::new(CacheBlock {
+            num: block,
+            data: chunks.next().unwrap().to_vec(),
+            dirty: false,
+            prefetched: false,
+        }));
+        self.insert(cache, block, b.clone())?;
+
+        if readahead > 0 {
+            self.readahead_batches.fetch_add(1, Ordering::Relaxed);
+        }
+        for (num, chunk) in (block + 1..).zip(chunks) {
+            if cache.contains_key(&num) || self.pinned.lock().contains_key(&num) {
+                // the cached block may be newer than what's on the device
+                continue;
+            }
+            self.insert(
+                cache,
+                num,
+                Arc::new(RwLock::new(CacheBlock {
+                    num,
+                    data: chunk.to_vec(),
+                    dirty: false,
+                    prefetched: true,
+                })),
+            )?;
+            self.readahead_prefetched.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(b)
+    }
+}
+
+impl<D, P> Drop for BlockCache<D, P>
+where
+    D: BlockDevice + Send + Sync,
+    P: CachePolicy<u64, CacheEntry>,
+{
+    fn drop(&mut self) {
+        let _ = self.sync();
+        // don't panic, even if the write fails
     }
 }
 
-impl<D> BlockDevice for BlockCache<D>
+impl<D, P> BlockDevice for &BlockCache<D, P>
 where
-    D: BlockDevice,
+    D: BlockDevice + Send + Sync,
+    P: CachePolicy<u64, CacheEntry>,
 {
     fn block_size(&self) -> usize {
         self.block_size
@@ -68,42 +485,222 @@ where
             return Err(Error::BufferTooSmall);
         }
 
-        let res = self.cache.lock().find(|b| b.read().num == block).cloned();
-        // cache.lock() must not live within the match because we may lock it again to insert a new block
-        let block = match res {
-            Some(b) => b,
-            None => {
-                let mut data = vec![0_u8; self.block_size];
-                let _ = self.device.read().read_block(block, &mut data)?;
-
-                let b = Rc::new(RwLock::new(CacheBlock {
-                    device: self.device.clone(),
-                    num: block,
-                    data,
-                }));
-                self.cache.lock().insert(b.clone());
-                b
+        let previous = self.last_read.swap(block, Ordering::Relaxed);
+        let sequential = block.checked_sub(1) == Some(previous);
+        let block = self.lookup(&mut self.cache.lock(), block, sequential)?;
+        self.touch(&block);
+        // the block may be evicted while we copy, but it still holds a coherent version of the data
+        buffer[..self.block_size].copy_from_slice(&block.read().data);
+
+        Ok(self.block_size)
+    }
+
+    fn write_block(&mut self, block: u64, buf: &dyn AsRef<[u8]>) -> Result<usize> {
+        let buffer = buf.as_ref();
+        if buffer.len() < self.block_size {
+            return Err(Error::BufferTooSmall);
+        }
+        let buffer = &buffer[..self.block_size];
+
+        let entry = {
+            let mut cache = self.cache.lock();
+            let cached = match cache.get(&block) {
+                Some(b) => Some(b.clone()),
+                None => self.restore_pinned(&mut cache, block)?,
+            };
+            match cached {
+                // pin the block, so that the write is not lost if the block is
+                // evicted before we can lock it
+                Some(b) => {
+                    self.pin(block, &b);
+                    b
+                }
+                None => {
+                    // we overwrite the full block, so there is no need to read it from the device
+                    self.insert(
+                        &mut cache,
+                        block,
+                        Arc::new(RwLock::new(CacheBlock {
+                            num: block,
+                            data: buffer.to_vec(),
+                            dirty: true,
+                            prefetched: false,
+                        })),
+                    )?;
+                    return Ok(self.block_size);
+                }
             }
         };
-        buffer.copy_from_slice(&block.read().data);
 
-        Ok(buffer.len())
+        {
+            let mut b = entry.write();
+            b.data.copy_from_slice(buffer);
+            b.dirty = true;
+            b.prefetched = false;
+        }
+        self.release(block)?;
+
+        Ok(self.block_size)
+    }
+}
+
+impl<D, P> BlockDevice for BlockCache<D, P>
+where
+    D: BlockDevice + Send + Sync,
+    P: CachePolicy<u64, CacheEntry>,
+{
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn block_count(&self) -> usize {
+        (&self).block_count()
+    }
+
+    fn read_block(&self, block: u64, buf: &mut dyn AsMut<[u8]>) -> Result<usize> {
+        (&self).read_block(block, buf)
     }
 
     fn write_block(&mut self, block: u64, buf: &dyn AsRef<[u8]>) -> Result<usize> {
-        self.device.write().write_block(block, buf)
+        BlockDevice::write_block(&mut &*self, block, buf)
+    }
+}
+
+/// A block of a [`BlockCache`] that is borrowed in place, returned by
+/// [`BlockCache::get_block`]. The block is pinned until the guard is dropped.
+pub struct BlockRef<'a, D, P>
+where
+    D: BlockDevice + Send + Sync,
+    P: CachePolicy<u64, CacheEntry>,
+{
+    cache: &'a BlockCache<D, P>,
+    num: u64,
+    /// Read locked for the lifetime of the guard.
+    entry: CacheEntry,
+}
+
+impl<D, P> BlockRef<'_, D, P>
+where
+    D: BlockDevice + Send + Sync,
+    P: CachePolicy<u64, CacheEntry>,
+{
+    /// The number of the borrowed block.
+    pub fn block(&self) -> u64 {
+        self.num
+    }
+}
+
+impl<D, P> Deref for BlockRef<'_, D, P>
+where
+    D: BlockDevice + Send + Sync,
+    P: CachePolicy<u64, CacheEntry>,
+{
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // SAFETY: we hold a read lock on the block until we are dropped
+        unsafe { &(*self.entry.as_mut_ptr()).data }
+    }
+}
+
+impl<D, P> Drop for BlockRef<'_, D, P>
+where
+    D: BlockDevice + Send + Sync,
+    P: CachePolicy<u64, CacheEntry>,
+{
+    fn drop(&mut self) {
+        // SAFETY: the read guard that was acquired in BlockCache::get_block was forgotten
+        unsafe { self.entry.force_read_decrement() };
+        // if this fails, the block is kept and written back by the next sync
+        let _ = self.cache.release(self.num);
+    }
+}
+
+/// A block of a [`BlockCache`] that is mutably borrowed in place, returned by
+/// [`BlockCache::get_block_mut`]. The block is pinned until the guard is dropped.
+pub struct BlockRefMut<'a, D, P>
+where
+    D: BlockDevice + Send + Sync,
+    P: CachePolicy<u64, CacheEntry>,
+{
+    cache: &'a BlockCache<D, P>,
+    num: u64,
+    /// Write locked for the lifetime of the guard.
+    entry: CacheEntry,
+}
+
+impl<D, P> BlockRefMut<'_, D, P>
+where
+    D: BlockDevice + Send + Sync,
+    P: CachePolicy<u64, CacheEntry>,
+{
+    /// The number of the borrowed block.
+    pub fn block(&self) -> u64 {
+        self.num
+    }
+}
+
+impl<D, P> Deref for BlockRefMut<'_, D, P>
+where
+    D: BlockDevice + Send + Sync,
+    P: CachePolicy<u64, CacheEntry>,
+{
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // SAFETY: we hold the write lock on the block until we are dropped
+        unsafe { &(*self.entry.as_mut_ptr()).data }
+    }
+}
+
+impl<D, P> DerefMut for BlockRefMut<'_, D, P>
+where
+    D: BlockDevice + Send + Sync,
+    P: CachePolicy<u64, CacheEntry>,
+{
+    fn deref_mut(&mut self) -> &mut [u8] {
+        // SAFETY: we hold the write lock on the block until we are dropped,
+        // and the returned reference borrows self mutably
+        let block = unsafe { &mut *self.entry.as_mut_ptr() };
+        block.dirty = true;
+        block.prefetched = false;
+        &mut block.data
+    }
+}
+
+impl<D, P> Drop for BlockRefMut<'_, D, P>
+where
+    D: BlockDevice + Send + Sync,
+    P: CachePolicy<u64, CacheEntry>,
+{
+    fn drop(&mut self) {
+        // SAFETY: the write guard that was acquired in BlockCache::get_block_mut was forgotten
+        unsafe { self.entry.force_write_unlock() };
+        // if this fails, the block is kept and written back by the next sync
+        let _ = self.cache.release(self.num);
     }
 }
 
 #[cfg(test)]
 mod tests {
     use alloc::vec;
+    use alloc::vec::Vec;
     use core::sync::atomic::Ordering;
 
-    use crate::io::block::cache::BlockCache;
+    use crate::collections::{ArcMap, CachePolicy, CacheStats, ClockMap, TwoQueueMap};
+    use crate::io::block::cache::{BlockCache, CacheEntry, ReadaheadStats};
+    use crate::io::block::memory::MemoryBlockDevice;
     use crate::io::block::one::OneDevice;
+    use crate::io::block::trace::{Call, Capture, TracingDevice};
     use crate::io::block::BlockDevice;
 
+    #[test]
+    fn test_cache_is_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<BlockCache<OneDevice>>();
+        assert_send_sync::<BlockCache<OneDevice, ArcMap<_, _>>>();
+    }
+
     #[test]
     fn test_cache_read() {
         let device = OneDevice::new(512, 1024);
@@ -133,4 +730,388 @@ mod tests {
             cache.device.read().block_size_count.load(Ordering::SeqCst)
         );
     }
+
+    #[test]
+    fn test_cache_write_back() {
+        let device = OneDevice::new(512, 1024);
+        let mut cache = BlockCache::new(device, 10);
+        let mut data = vec![2_u8; cache.block_size()];
+        for block_num in [1, 2, 3, 1] {
+            cache.write_block(block_num, &data).unwrap();
+        }
+        // writes only go to the cache, and reads of written blocks are served from it
+        cache.read_block(1, &mut data).unwrap();
+        assert_eq!(vec![2_u8; 512], data);
+        assert_eq!(3, cache.dirty_count());
+        assert_eq!(
+            0,
+            cache.device.read().write_block_count.load(Ordering::SeqCst)
+        );
+        assert_eq!(
+            0,
+            cache.device.read().read_block_count.load(Ordering::SeqCst)
+        );
+
+        cache.sync().unwrap();
+        assert_eq!(0, cache.dirty_count());
+        assert_eq!(
+            3,
+            cache.device.read().write_block_count.load(Ordering::SeqCst)
+        );
+
+        // nothing is dirty anymore, so syncing again doesn't touch the device
+        cache.sync().unwrap();
+        assert_eq!(
+            3,
+            cache.device.read().write_block_count.load(Ordering::SeqCst)
+        );
+    }
+
+    #[test]
+    fn test_cache_evict_only_dirty() {
+        let device = OneDevice::new(512, 1024);
+        let mut cache = BlockCache::new(device, 2);
+        let mut data = vec![0_u8; cache.block_size()];
+        cache.read_block(1, &mut data).unwrap();
+        cache.write_block(2, &data).unwrap();
+        // evicts the clean block 1
+        cache.read_block(3, &mut data).unwrap();
+        assert_eq!(
+            0,
+            cache.device.read().write_block_count.load(Ordering::SeqCst)
+        );
+        // evicts the dirty block 2
+        cache.read_block(4, &mut data).unwrap();
+        assert_eq!(
+            1,
+            cache.device.read().write_block_count.load(Ordering::SeqCst)
+        );
+        assert_eq!(0, cache.dirty_count());
+    }
+
+    #[test]
+    fn test_cache_scan_resistant_policy() {
+        let cache = BlockCache::with_policy(OneDevice::new(512, 1024), ArcMap::new(4));
+        let mut data = vec![0_u8; cache.block_size()];
+        // metadata blocks that are used over and over again
+        for block_num in [0, 1, 0, 1] {
+            cache.read_block(block_num, &mut data).unwrap();
+        }
+        for block_num in 100..200 {
+            cache.read_block(block_num, &mut data).unwrap();
+        }
+        cache.read_block(0, &mut data).unwrap();
+        cache.read_block(1, &mut data).unwrap();
+        assert_eq!(
+            102,
+            cache.device.read().read_block_count.load(Ordering::SeqCst)
+        );
+    }
+
+    #[test]
+    fn test_cache_policies_write_back() {
+        fn check<P>(mut cache: BlockCache<MemoryBlockDevice, P>)
+        where
+            P: CachePolicy<u64, CacheEntry>,
+        {
+            for block_num in 0..16 {
+                cache.write_block(block_num, &[block_num as u8; 4]).unwrap();
+            }
+            let mut data = [0_u8; 4];
+            for block_num in 0..16 {
+                cache.read_block(block_num, &mut data).unwrap();
+                assert_eq!([block_num as u8; 4], data);
+            }
+            cache.sync().unwrap();
+            assert_eq!(0, cache.dirty_count());
+            let device = cache.device.read();
+            for block_num in 0..16 {
+                assert_eq!([block_num as u8; 4], device.get_ref()[block_num * 4..][..4]);
+            }
+        }
+
+        check(BlockCache::new(MemoryBlockDevice::new(4, 16), 3));
+        check(BlockCache::with_policy(
+            MemoryBlockDevice::new(4, 16),
+            ClockMap::new(3),
+        ));
+        check(BlockCache::with_policy(
+            MemoryBlockDevice::new(4, 16),
+            TwoQueueMap::new(3),
+        ));
+        check(BlockCache::with_policy(
+            MemoryBlockDevice::new(4, 16),
+            ArcMap::new(3),
+        ));
+    }
+
+    #[test]
+    fn test_cache_stats() {
+        let device = OneDevice::new(512, 1024);
+        let mut cache = BlockCache::new(device, 2);
+        let mut data = vec![0_u8; cache.block_size()];
+        cache.read_block(1, &mut data).unwrap();
+        cache.write_block(2, &data).unwrap();
+        cache.read_block(1, &mut data).unwrap();
+        // evicts the dirty block 2, then the clean block 1
+        cache.read_block(3, &mut data).unwrap();
+        cache.read_block(4, &mut data).unwrap();
+        cache.write_block(3, &data).unwrap();
+        assert_eq!(
+            CacheStats {
+                hits: 2,
+                misses: 4,
+                evictions: 2,
+                writebacks: 1,
+                dirty: 1,
+                occupancy: 2,
+                capacity: 2,
+            },
+            cache.stats()
+        );
+
+        cache.reset_stats();
+        assert_eq!(
+            CacheStats {
+                dirty: 1,
+                occupancy: 2,
+                capacity: 2,
+                ..CacheStats::default()
+            },
+            cache.stats()
+        );
+    }
+
+    #[test]
+    fn test_cache_get_block() {
+        let device = MemoryBlockDevice::from_image((0..32).collect::<Vec<u8>>(), 8);
+        let cache = BlockCache::new(device, 2);
+        let block = cache.get_block(2).unwrap();
+        assert_eq!(2, block.block());
+        assert_eq!(&[16, 17, 18, 19, 20, 21, 22, 23], &block[..]);
+        // other readers are not blocked
+        let again = cache.get_block(2).unwrap();
+        assert_eq!(block[..], again[..]);
+        let mut data = [0_u8; 8];
+        cache.read_block(2, &mut data).unwrap();
+        assert_eq!(block[..], data);
+        drop(block);
+        drop(again);
+        assert_eq!(0, cache.dirty_count());
+    }
+
+    #[test]
+    fn test_cache_sync_with_block_ref() {
+        let device = MemoryBlockDevice::from_image(vec![0_u8; 32], 8);
+        let mut cache = BlockCache::new(device, 4);
+        cache.write_block(1, &[1_u8; 8]).unwrap();
+        let clean = cache.get_block(0).unwrap();
+        let dirty = cache.get_block(1).unwrap();
+        cache.sync().unwrap();
+        assert_eq!([1_u8; 8], cache.device.read().get_ref()[8..16]);
+        // the pinned block could not be marked as clean
+        assert_eq!(1, cache.dirty_count());
+
+        drop(clean);
+        drop(dirty);
+        cache.sync().unwrap();
+        assert_eq!(0, cache.dirty_count());
+    }
+
+    #[test]
+    fn test_cache_get_block_mut() {
+        let cache = BlockCache::new(MemoryBlockDevice::new(4, 4), 2);
+        let mut block = cache.get_block_mut(1).unwrap();
+        block.copy_from_slice(&[1, 2, 3, 4]);
+        drop(block);
+        assert_eq!(1, cache.dirty_count());
+
+        // reading through a mutable guard doesn't mark the block dirty
+        cache.sync().unwrap();
+        let block = cache.get_block_mut(1).unwrap();
+        assert_eq!(&[1, 2, 3, 4], &block[..]);
+        drop(block);
+        assert_eq!(0, cache.dirty_count());
+        assert_eq!(&[1, 2, 3, 4], &cache.device.read().get_ref()[4..8]);
+    }
+
+    #[test]
+    fn test_cache_pinned_block_survives_eviction() {
+        let cache = BlockCache::new(MemoryBlockDevice::new(4, 8), 2);
+        let mut block = cache.get_block_mut(0).unwrap();
+        block.fill(9);
+        let mut data = [0_u8; 4];
+        for block_num in 1..8 {
+            cache.read_block(block_num, &mut data).unwrap();
+        }
+        // evicted, but still pinned, so neither dropped nor written back
+        assert_eq!([9_u8; 4], block[..]);
+        assert_eq!([0_u8; 4], cache.device.read().get_ref()[..4]);
+
+        // the last pin of an evicted block writes it back
+        drop(block);
+        assert_eq!([9_u8; 4], cache.device.read().get_ref()[..4]);
+        assert_eq!(0, cache.dirty_count());
+    }
+
+    #[test]
+    fn test_cache_pinned_block_found_after_eviction() {
+        let mut cache = BlockCache::new(MemoryBlockDevice::new(4, 8), 2);
+        cache.write_block(0, &[9_u8; 4]).unwrap();
+        let block = cache.get_block(0).unwrap();
+        let mut data = [0_u8; 4];
+        for block_num in 1..8 {
+            cache.read_block(block_num, &mut data).unwrap();
+        }
+        // served from the evicted block, not from the outdated device
+        cache.read_block(0, &mut data).unwrap();
+        assert_eq!([9_u8; 4], data);
+        drop(block);
+        assert_eq!(1, cache.dirty_count());
+        cache.sync().unwrap();
+        assert_eq!([9_u8; 4], cache.device.read().get_ref()[..4]);
+    }
+
+    #[test]
+    fn test_cache_write_pinned_block_counts_one_miss() {
+        let cache = BlockCache::new(MemoryBlockDevice::new(4, 8), 2);
+        let block = cache.get_block(0).unwrap();
+        let mut data = [0_u8; 4];
+        for block_num in 1..3 {
+            cache.read_block(block_num, &mut data).unwrap();
+        }
+        cache.reset_stats();
+
+        std::thread::scope(|s| {
+            let writer = s.spawn(|| {
+                let mut dev = &cache;
+                dev.write_block(0, &[9_u8; 4]).unwrap();
+            });
+            // the writer looks up the evicted block, then waits for the guard
+            while cache.stats().misses == 0 {
+                std::thread::yield_now();
+            }
+            drop(block);
+            writer.join().unwrap();
+        });
+        assert_eq!(1, cache.stats().misses);
+        assert_eq!(0, cache.stats().hits);
+        cache.read_block(0, &mut data).unwrap();
+        assert_eq!([9_u8; 4], data);
+    }
+
+    #[test]
+    fn test_cache_shared_between_threads() {
+        const THREADS: usize = 4;
+        const BLOCKS_PER_THREAD: usize = 16;
+        const ROUNDS: u8 = 50;
+
+        let device = MemoryBlockDevice::new(16, THREADS * BLOCKS_PER_THREAD);
+        // much smaller than the working set, so that the threads constantly evict each other's blocks
+        let cache = BlockCache::new(device, 8);
+
+        std::thread::scope(|s| {
+            for t in 0..THREADS {
+                let cache = &cache;
+                s.spawn(move || {
+                    let mut dev = cache;
+                    let mut data = vec![0_u8; 16];
+                    for round in 0..ROUNDS {
+                        for i in 0..BLOCKS_PER_THREAD {
+                            let block = (t * BLOCKS_PER_THREAD + i) as u64;
+                            dev.write_block(block, &[t as u8, round, i as u8].repeat(6))
+                                .unwrap();
+                        }
+                        for i in 0..BLOCKS_PER_THREAD {
+                            let block = (t * BLOCKS_PER_THREAD + i) as u64;
+                            dev.read_block(block, &mut data).unwrap();
+                            assert_eq!(&[t as u8, round, i as u8].repeat(6)[..16], &data[..]);
+                        }
+                    }
+                });
+            }
+        });
+
+        cache.sync().unwrap();
+        let device = cache.device.read();
+        for t in 0..THREADS {
+            for i in 0..BLOCKS_PER_THREAD {
+                assert_eq!(
+                    &[t as u8, ROUNDS - 1, i as u8].repeat(6)[..16],
+                    &device.get_ref()[(t * BLOCKS_PER_THREAD + i) * 16..][..16]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_cache_readahead() {
+        let device = TracingDevice::new(OneDevice::new(512, 1024), Capture::Calls);
+        let cache = BlockCache::new(device, 16);
+        cache.set_readahead(4);
+        let mut data = vec![0_u8; cache.block_size()];
+        for block_num in 0..10 {
+            cache.read_block(block_num, &mut data).unwrap();
+        }
+        /*
+        0: no hit, not sequential, load only block 0
+        1: no hit, sequential, load blocks 1 to 5 in one batch
+        2-5: readahead hits
+        6: no hit, sequential, load blocks 6 to 10 in one batch
+        7-9: readahead hits
+         */
+        let reads: Vec<_> = cache
+            .device
+            .read()
+            .trace()
+            .into_iter()
+            .map(|entry| entry.call)
+            .filter(|call| matches!(call, Call::ReadBlock { .. } | Call::ReadBlocks { .. }))
+            .collect();
+        assert_eq!(
+            vec![
+                Call::ReadBlock { block: 0 },
+                Call::ReadBlocks { start: 1, count: 5 },
+                Call::ReadBlocks { start: 6, count: 5 },
+            ],
+            reads
+        );
+        assert_eq!(
+            ReadaheadStats {
+                batches: 2,
+                prefetched: 8,
+                hits: 7,
+            },
+            cache.readahead_stats()
+        );
+    }
+
+    #[test]
+    fn test_cache_readahead_keeps_cached_blocks() {
+        let mut cache = BlockCache::new(MemoryBlockDevice::new(4, 16), 8);
+        cache.set_readahead(4);
+        cache.write_block(3, &[7_u8; 4]).unwrap();
+
+        let mut data = [0_u8; 4];
+        for block_num in 0..5 {
+            cache.read_block(block_num, &mut data).unwrap();
+        }
+        cache.read_block(3, &mut data).unwrap();
+        assert_eq!([7_u8; 4], data);
+        assert_eq!(1, cache.dirty_count());
+        // block 3 was already cached, so it wasn't prefetched
+        assert_eq!(3, cache.readahead_stats().prefetched);
+    }
+
+    #[test]
+    fn test_cache_readahead_end_of_device() {
+        let device = OneDevice::new(512, 4);
+        let cache = BlockCache::new(device, 16);
+        cache.set_readahead(8);
+        let mut data = vec![0_u8; cache.block_size()];
+        for block_num in 0..4 {
+            cache.read_block(block_num, &mut data).unwrap();
+        }
+        assert_eq!(2, cache.readahead_stats().prefetched);
+    }
 }
@@ -0,0 +1,484 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::compression::{compress, decompress};
+use crate::io::block::BlockDevice;
+use crate::io::{Error, Result};
+
+const MAGIC: &[u8; 8] = b"KSTDLZ77";
+const VERSION: u32 = 1;
+/// The size of the header fields in the first block.
+const HEADER_SIZE: usize = 24;
+/// The size of an entry in the allocation map.
+const ENTRY_SIZE: usize = 8;
+
+/// Where a block is stored on the inner device.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+struct Entry {
+    /// The first block of the data, relative to the start of the data area.
+    start: u32,
+    /// The number of stored bytes. Zero means that the block was never written
+    /// or only contains zeros, and the full block size means that the data
+    /// is stored uncompressed.
+    len: u32,
+}
+
+/// A [`BlockDevice`] that stores its blocks compressed on the inner device.
+///
+/// Every block of this device spans a fixed number of blocks of the inner
+/// device. It is compressed with [`crate::compression::lz77`] and stored in as
+/// few consecutive inner blocks as needed, or uncompressed if compression
+/// doesn't save a whole inner block. Blocks that only contain zeros take no
+/// space at all. This device can therefore offer more blocks than fit onto the
+/// inner device uncompressed, and a write fails with [`Error::WriteError`] if
+/// the inner device runs out of space.
+///
+/// The first block of the inner device is a header, followed by an allocation
+/// map with the location and compressed size of every block, and the data area.
+/// A rewritten block is stored in free space, and the allocation map is updated
+/// afterwards, so the old data remains readable if the write fails. Only if
+/// there is no other space, the new data is stored over the old data, which
+/// then isn't crash consistent.
+///
+/// ```
+/// # use kstd::io::block::BlockDevice;
+/// # use kstd::io::block::compressed::CompressedDevice;
+/// # use kstd::io::block::memory::MemoryBlockDevice;
+/// // 4 KiB blocks on a device with 512 byte blocks
+/// let mut device = CompressedDevice::format(MemoryBlockDevice::new(512, 64), 8, 100).unwrap();
+/// assert_eq!(4096, device.block_size());
+/// device.write_block(99, &[b'a'; 4096]).unwrap();
+/// assert_eq!(1, device.used_blocks());
+/// ```
+pub struct CompressedDevice<D>
+where
+    D: BlockDevice,
+{
+    inner: D,
+    /// The number of inner blocks per block of this device.
+    blocks_per_block: usize,
+    entries: Vec<Entry>,
+    /// The first block of the data area on the inner device.
+    data_start: u64,
+    /// Whether a block of the data area is in use.
+    used: Vec<bool>,
+}
+
+impl<D> CompressedDevice<D>
+where
+    D: BlockDevice,
+{
+    /// Creates an empty device with `block_count` blocks, each of which spans
+    /// `blocks_per_block` blocks of the given device. All blocks read as zeros.
+    ///
+    /// Fails with [`Error::InvalidArgument`] if the block size of the inner
+    /// device is not a multiple of 8 or smaller than 24 bytes, or if there is
+    /// no space left for data after the allocation map.
+    pub fn format(inner: D, blocks_per_block: usize, block_count: usize) -> Result<Self> {
+        let mut device = Self::new(inner, blocks_per_block, block_count)?;
+
+        let mut header = vec![0_u8; device.inner.block_size()];
+        header[0..8].copy_from_slice(MAGIC);
+        header[8..12].copy_from_slice(&VERSION.to_le_bytes());
+        header[12..16].copy_from_slice(&(blocks_per_block as u32).to_le_bytes());
+        header[16..24].copy_from_slice(&(block_count as u64).to_le_bytes());
+        let _ = device.inner.write_block(0, &header)?;
+        for index in 0..device.map_blocks() {
+            device.write_map_block(index)?;
+        }
+        Ok(device)
+    }
+
+    /// Opens a device that was created with [`CompressedDevice::format`].
+    ///
+    /// Returns [`Error::InvalidMagicNumber`] if there is no such device, and
+    /// [`Error::IncoherentData`] if the allocation map is inconsistent.
+    pub fn open(inner: D) -> Result<Self> {
+        let mut header = vec![0_u8; inner.block_size().max(HEADER_SIZE)];
+        let _ = inner.read_block(0, &mut header)?;
+        if &header[0..8] != MAGIC {
+            return Err(Error::InvalidMagicNumber);
+        }
+        if u32::from_le_bytes(header[8..12].try_into().unwrap()) != VERSION {
+            return Err(Error::IncoherentData);
+        }
+        let blocks_per_block = u32::from_le_bytes(header[12..16].try_into().unwrap()) as usize;
+        let block_count = u64::from_le_bytes(header[16..24].try_into().unwrap()) as usize;
+        let mut device =
+            Self::new(inner, blocks_per_block, block_count).map_err(|_| Error::IncoherentData)?;
+
+        let inner_block_size = device.inner.block_size();
+        let per_block = inner_block_size / ENTRY_SIZE;
+        let mut data = vec![0_u8; inner_block_size];
+        for index in 0..device.map_blocks() {
+            let _ = device.inner.read_block(1 + index as u64, &mut data)?;
+            let start = index * per_block;
+            let end = (start + per_block).min(block_count);
+            for (entry, bytes) in device.entries[start..end]
+                .iter_mut()
+                .zip(data.as_chunks::<ENTRY_SIZE>().0)
+            {
+                entry.start = u32::from_le_bytes(bytes[0..4].try_into().unwrap());
+                entry.len = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+            }
+        }
+
+        for num in 0..block_count {
+            let entry = device.entries[num];
+            if entry.len as usize > device.block_size() {
+                return Err(Error::IncoherentData);
+            }
+            let start = entry.start as usize;
+            let end = start + device.stored_blocks(entry);
+            if end > device.used.len() || device.used[start..end].contains(&true) {
+                return Err(Error::IncoherentData);
+            }
+            device.used[start..end].fill(true);
+        }
+        Ok(device)
+    }
+
+    fn new(inner: D, blocks_per_block: usize, block_count: usize) -> Result<Self> {
+        let inner_block_size = inner.block_size();
+        if inner_block_size < HEADER_SIZE
+            || !inner_block_size.is_multiple_of(ENTRY_SIZE)
+            || blocks_per_block == 0
+            || blocks_per_block
+                .checked_mul(inner_block_size)
+                .is_none_or(|size| size > u32::MAX as usize)
+        {
+            return Err(Error::InvalidArgument);
+        }
+        let map_blocks = block_count
+            .checked_mul(ENTRY_SIZE)
+            .ok_or(Error::InvalidArgument)?
+            .div_ceil(inner_block_size);
+        let data_start = 1 + map_blocks;
+        let data_blocks = inner
+            .block_count()
+            .checked_sub(data_start)
+            .filter(|&n| n > 0 && n <= u32::MAX as usize)
+            .ok_or(Error::InvalidArgument)?;
+        Ok(Self {
+            inner,
+            blocks_per_block,
+            entries: vec![Entry::default(); block_count],
+            data_start: data_start as u64,
+            used: vec![false; data_blocks],
+        })
+    }
+
+    pub fn get_ref(&self) -> &D {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    /// The number of blocks of the inner device that hold data.
+    pub fn used_blocks(&self) -> usize {
+        self.used.iter().filter(|&&used| used).count()
+    }
+
+    fn map_blocks(&self) -> usize {
+        (self.entries.len() * ENTRY_SIZE).div_ceil(self.inner.block_size())
+    }
+
+    /// The number of inner blocks that the data of the given entry occupies.
+    fn stored_blocks(&self, entry: Entry) -> usize {
+        (entry.len as usize).div_ceil(self.inner.block_size())
+    }
+
+    /// Writes the block of the allocation map with the given index to the inner device.
+    fn write_map_block(&mut self, index: usize) -> Result<()> {
+        let inner_block_size = self.inner.block_size();
+        let per_block = inner_block_size / ENTRY_SIZE;
+        let start = index * per_block;
+        let end = (start + per_block).min(self.entries.len());
+        let mut data = vec![0_u8; inner_block_size];
+        for (bytes, entry) in data
+            .as_chunks_mut::<ENTRY_SIZE>()
+            .0
+            .iter_mut()
+            .zip(&self.entries[start..end])
+        {
+            bytes[0..4].copy_from_slice(&entry.start.to_le_bytes());
+            bytes[4..8].copy_from_slice(&entry.len.to_le_bytes());
+        }
+        let _ = self.inner.write_block(1 + index as u64, &data)?;
+        Ok(())
+    }
+
+    /// Finds the first run of `count` free blocks in the data area.
+    fn allocate(&self, count: usize) -> Option<usize> {
+        let mut run = 0;
+        for (i, &used) in self.used.iter().enumerate() {
+            run = if used { 0 } else { run + 1 };
+            if run == count {
+                return Some(i + 1 - count);
+            }
+        }
+        None
+    }
+}
+
+impl<D> BlockDevice for CompressedDevice<D>
+where
+    D: BlockDevice,
+{
+    fn block_size(&self) -> usize {
+        self.blocks_per_block * self.inner.block_size()
+    }
+
+    fn block_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn read_block(&self, block: u64, buf: &mut dyn AsMut<[u8]>) -> Result<usize> {
+        let entry = *self.entries.get(block as usize).ok_or(Error::NoSuchBlock)?;
+        let block_size = self.block_size();
+        let buffer = buf.as_mut();
+        if buffer.len() < block_size {
+            return Err(Error::BufferTooSmall);
+        }
+        let buffer = &mut buffer[..block_size];
+
+        let count = self.stored_blocks(entry);
+        let start = self.data_start + entry.start as u64;
+        if entry.len == 0 {
+            buffer.fill(0);
+        } else if entry.len as usize == block_size {
+            let _ = self.inner.read_blocks(start, count, &mut &mut *buffer)?;
+        } else {
+            let mut data = vec![0_u8; count * self.inner.block_size()];
+            let _ = self.inner.read_blocks(start, count, &mut data)?;
+            if decompress(&data[..entry.len as usize], buffer)? != block_size {
+                return Err(Error::DecodeError);
+            }
+        }
+        Ok(block_size)
+    }
+
+    fn write_block(&mut self, block: u64, buf: &dyn AsRef<[u8]>) -> Result<usize> {
+        let old = *self.entries.get(block as usize).ok_or(Error::NoSuchBlock)?;
+        let block_size = self.block_size();
+        let inner_block_size = self.inner.block_size();
+        let buffer = buf.as_ref();
+        if buffer.len() < block_size {
+            return Err(Error::BufferTooSmall);
+        }
+        let buffer = &buffer[..block_size];
+
+        let mut data = if buffer.iter().all(|&b| b == 0) {
+            Vec::new()
+        } else {
+            let compressed = compress(buffer);
+            if compressed.len().div_ceil(inner_block_size) < self.blocks_per_block {
+                compressed
+            } else {
+                buffer.to_vec()
+            }
+        };
+        let len = data.len();
+        data.resize(len.next_multiple_of(inner_block_size), 0);
+
+        let old_range = old.start as usize..old.start as usize + self.stored_blocks(old);
+        let count = data.len() / inner_block_size;
+        // blocks of zeros are not stored
+        let start = if count == 0 {
+            0
+        } else {
+            // free space is preferred, so that the old data stays intact until
+            // the allocation map points to the new data
+            let start = match self.allocate(count) {
+                Some(start) => start,
+                None => {
+                    // the new data can only be stored over the old data
+                    self.used[old_range.clone()].fill(false);
+                    let start = self.allocate(count);
+                    self.used[old_range.clone()].fill(true);
+                    start.ok_or(Error::WriteError)?
+                }
+            };
+            let _ = self
+                .inner
+                .write_blocks(self.data_start + start as u64, count, &data)?;
+            start
+        };
+
+        // the space of the old data is only released once the allocation map
+        // on the inner device points to the new data
+        self.entries[block as usize] = Entry {
+            start: start as u32,
+            len: len as u32,
+        };
+        if let Err(e) = self.write_map_block(block as usize * ENTRY_SIZE / inner_block_size) {
+            self.entries[block as usize] = old;
+            return Err(e);
+        }
+        self.used[old_range].fill(false);
+        self.used[start..start + count].fill(true);
+        Ok(block_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use crate::io::block::compressed::CompressedDevice;
+    use crate::io::block::faulty::{Fault, FaultyDevice, Operation, Trigger};
+    use crate::io::block::memory::MemoryBlockDevice;
+    use crate::io::block::BlockDevice;
+    use crate::io::{Error, ReadAt, WriteAt};
+
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x9E37_79B9_u32;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_compressed_layout() {
+        // 32 entries need 4 map blocks, which leaves 11 blocks for data
+        let device = CompressedDevice::format(MemoryBlockDevice::new(64, 16), 4, 32).unwrap();
+        assert_eq!(256, device.block_size());
+        assert_eq!(32, device.block_count());
+        assert_eq!(11, device.used.len());
+        assert!(matches!(
+            CompressedDevice::format(MemoryBlockDevice::new(64, 5), 4, 32),
+            Err(Error::InvalidArgument)
+        ));
+        assert!(matches!(
+            CompressedDevice::format(MemoryBlockDevice::new(20, 16), 4, 32),
+            Err(Error::InvalidArgument)
+        ));
+    }
+
+    #[test]
+    fn test_compressed_read_write() {
+        let mut device = CompressedDevice::format(MemoryBlockDevice::new(64, 16), 4, 32).unwrap();
+        let mut data = [0_u8; 256];
+        assert_eq!(Ok(256), device.read_block(31, &mut data));
+        assert_eq!([0_u8; 256], data);
+
+        let text = b"all work and no play makes jack a dull boy. ".repeat(5);
+        device.write_at(256, &text).unwrap();
+        // compresses into a single inner block
+        assert_eq!(1, device.used_blocks());
+        let random = noise(256);
+        device.write_block(2, &random).unwrap();
+        // stored uncompressed
+        assert_eq!(5, device.used_blocks());
+
+        let mut read = vec![0_u8; text.len()];
+        device.read_at(256, &mut read).unwrap();
+        assert_eq!(text, read);
+        device.read_block(2, &mut data).unwrap();
+        assert_eq!(random, data);
+
+        // writing zeros frees the space
+        device.write_block(2, &[0_u8; 256]).unwrap();
+        assert_eq!(1, device.used_blocks());
+        assert_eq!(
+            Err(Error::NoSuchBlock),
+            device.write_block(32, &[0_u8; 256])
+        );
+    }
+
+    #[test]
+    fn test_compressed_write_zeros() {
+        let mut device = CompressedDevice::format(MemoryBlockDevice::new(64, 16), 4, 32).unwrap();
+        assert_eq!(Ok(256), device.write_block(3, &[0_u8; 256]));
+        assert_eq!(0, device.used_blocks());
+
+        // zeroing the only stored block
+        device.write_block(3, &[1_u8; 256]).unwrap();
+        assert_eq!(Ok(256), device.write_block(3, &[0_u8; 256]));
+        assert_eq!(0, device.used_blocks());
+        let mut data = [1_u8; 256];
+        device.read_block(3, &mut data).unwrap();
+        assert_eq!([0_u8; 256], data);
+    }
+
+    #[test]
+    fn test_compressed_failed_rewrite_keeps_old_data() {
+        let mut faulty = FaultyDevice::new(MemoryBlockDevice::new(64, 16));
+        // the header and 4 map blocks, then 4 data blocks and a map block,
+        // then a failed data block, and 4 data blocks and a failed map block
+        for n in [10, 15] {
+            faulty.inject(
+                Operation::Write,
+                Trigger::Nth(n),
+                Fault::Fail(Error::WriteError),
+            );
+        }
+        let mut device = CompressedDevice::format(faulty, 4, 32).unwrap();
+        let old = noise(256);
+        device.write_block(0, &old).unwrap();
+
+        let new: Vec<u8> = old.iter().map(|b| !b).collect();
+        let mut data = [0_u8; 256];
+        for _ in 0..2 {
+            assert_eq!(Err(Error::WriteError), device.write_block(0, &new));
+            device.read_block(0, &mut data).unwrap();
+            assert_eq!(old, data);
+            assert_eq!(4, device.used_blocks());
+        }
+
+        let mut device = CompressedDevice::open(device.into_inner()).unwrap();
+        device.read_block(0, &mut data).unwrap();
+        assert_eq!(old, data);
+        assert_eq!(4, device.used_blocks());
+
+        device.write_block(0, &new).unwrap();
+        device.read_block(0, &mut data).unwrap();
+        assert_eq!(new, data);
+        assert_eq!(4, device.used_blocks());
+        // the new data was stored next to the old data, which is still intact
+        assert_eq!(old, device.get_ref().get_ref().get_ref()[320..576]);
+    }
+
+    #[test]
+    fn test_compressed_out_of_space() {
+        let mut device = CompressedDevice::format(MemoryBlockDevice::new(64, 16), 4, 32).unwrap();
+        for block in 0..2 {
+            device.write_block(block, &noise(256)).unwrap();
+        }
+        assert_eq!(Err(Error::WriteError), device.write_block(2, &noise(256)));
+        // a rewrite can reuse the space of the old data
+        device.write_block(1, &noise(256)).unwrap();
+        // compressible data still fits
+        device.write_block(2, &[1_u8; 256]).unwrap();
+        assert_eq!(9, device.used_blocks());
+    }
+
+    #[test]
+    fn test_compressed_open() {
+        let mut device = CompressedDevice::format(MemoryBlockDevice::new(64, 16), 4, 32).unwrap();
+        device.write_block(20, &[3_u8; 256]).unwrap();
+        device.write_block(5, &noise(256)).unwrap();
+
+        let device = CompressedDevice::open(device.into_inner()).unwrap();
+        assert_eq!(32, device.block_count());
+        assert_eq!(5, device.used_blocks());
+        let mut data = [0_u8; 256];
+        device.read_block(20, &mut data).unwrap();
+        assert_eq!([3_u8; 256], data);
+        device.read_block(5, &mut data).unwrap();
+        assert_eq!(noise(256), data);
+
+        assert!(matches!(
+            CompressedDevice::open(MemoryBlockDevice::new(64, 16)),
+            Err(Error::InvalidMagicNumber)
+        ));
+    }
+}
@@ -1,35 +1,91 @@
 use alloc::collections::BTreeMap;
-use alloc::rc::Rc;
-
-use crate::sync::Mutex;
-use crate::sync::RwLock;
+use alloc::vec::Vec;
 
 use crate::io::block::BlockDevice;
 use crate::io::{Error, Result};
 
-#[derive(Clone)]
-struct Block(Rc<RwLock<[u8; 512]>>);
-
-impl Block {
-    pub fn new() -> Self {
-        Self(Rc::new(RwLock::new([0_u8; 512])))
-    }
-}
-
+/// A copy-on-write overlay over another [`BlockDevice`].
+///
+/// Writes never reach the inner device, they are kept in memory instead.
+/// Reads of blocks that were written return the written data, all other
+/// reads fall through to the inner device. The staged blocks can be written
+/// to the inner device with [`CowBlockDevice::commit`], or thrown away with
+/// [`CowBlockDevice::discard`].
 pub struct CowBlockDevice<D>
 where
-    D: BlockDevice,
+    D: BlockDevice + Send + Sync,
 {
     inner: D,
-    blocks: Mutex<BTreeMap<u64, Block>>,
+    block_size: usize,
+    blocks: BTreeMap<u64, Vec<u8>>,
+}
+
+impl<D> CowBlockDevice<D>
+where
+    D: BlockDevice + Send + Sync,
+{
+    pub fn new(inner: D) -> Self {
+        Self {
+            block_size: inner.block_size(),
+            inner,
+            blocks: BTreeMap::new(),
+        }
+    }
+
+    pub fn get_ref(&self) -> &D {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut D {
+        &mut self.inner
+    }
+
+    /// Returns the inner device. All staged blocks are discarded.
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    /// Returns the numbers of all blocks that have been written to this
+    /// device, but not to the inner device, in ascending order.
+    pub fn dirty_blocks(&self) -> impl Iterator<Item = u64> + '_ {
+        self.blocks.keys().copied()
+    }
+
+    /// Writes all staged blocks to the inner device in ascending order.
+    /// If a write fails, the error is returned, and the failed block as
+    /// well as all blocks after it remain staged.
+    pub fn commit(&mut self) -> Result<()> {
+        while let Some((num, data)) = self.blocks.pop_first() {
+            if let Err(e) = self.inner.write_block(num, &data) {
+                self.blocks.insert(num, data);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Throws away all staged blocks, so that this device
+    /// reads the same data as the inner device again.
+    pub fn discard(&mut self) {
+        self.blocks.clear();
+    }
+
+    /// Removes all staged blocks from this device and returns them.
+    pub(crate) fn take_blocks(&mut self) -> BTreeMap<u64, Vec<u8>> {
+        core::mem::take(&mut self.blocks)
+    }
+
+    pub(crate) fn blocks_mut(&mut self) -> &mut BTreeMap<u64, Vec<u8>> {
+        &mut self.blocks
+    }
 }
 
 impl<D> BlockDevice for CowBlockDevice<D>
 where
-    D: BlockDevice,
+    D: BlockDevice + Send + Sync,
 {
     fn block_size(&self) -> usize {
-        self.inner.block_size()
+        self.block_size
     }
 
     fn block_count(&self) -> usize {
@@ -38,44 +94,97 @@ where
 
     fn read_block(&self, block: u64, buf: &mut dyn AsMut<[u8]>) -> Result<usize> {
         let buffer = buf.as_mut();
-        let block_size = self.block_size();
+        let block_size = self.block_size;
         if buffer.len() < block_size {
             return Err(Error::BufferTooSmall);
         }
 
-        if let Some(b) = self.blocks.lock().get(&block).cloned() {
-            buffer[0..block_size].copy_from_slice(b.0.read().as_slice());
+        if let Some(b) = self.blocks.get(&block) {
+            buffer[0..block_size].copy_from_slice(b);
             return Ok(block_size);
         }
 
-        Err(Error::NoSuchBlock)
+        self.inner
+            .read_block(block, &mut &mut buffer[0..block_size])
     }
 
     fn write_block(&mut self, block: u64, buf: &dyn AsRef<[u8]>) -> Result<usize> {
         let buffer = buf.as_ref();
-        let block_size = self.block_size();
+        let block_size = self.block_size;
         if buffer.len() < block_size {
             return Err(Error::BufferTooSmall);
         }
-
-        if !self.blocks.lock().contains_key(&block) {
-            self.load_block(block)?;
+        if block >= self.block_count() as u64 {
+            return Err(Error::NoSuchBlock);
         }
 
-        let b = self.blocks.lock().get(&block).cloned().unwrap();
-        b.0.write()[0..block_size].copy_from_slice(buffer);
+        match self.blocks.get_mut(&block) {
+            Some(b) => b.copy_from_slice(&buffer[0..block_size]),
+            None => {
+                // we overwrite the full block, so there is no need to read it from the inner device
+                self.blocks.insert(block, buffer[0..block_size].to_vec());
+            }
+        }
         Ok(block_size)
     }
 }
 
-impl<D> CowBlockDevice<D>
-where
-    D: BlockDevice,
-{
-    fn load_block(&self, block: u64) -> Result<usize> {
-        let b = Block::new();
-        self.inner.read_block(block, &mut b.0.write().as_mut())?;
-        self.blocks.lock().insert(block, b);
-        Ok(0)
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use core::sync::atomic::Ordering;
+
+    use crate::io::block::cow::CowBlockDevice;
+    use crate::io::block::one::OneDevice;
+    use crate::io::block::BlockDevice;
+    use crate::io::Error;
+
+    #[test]
+    fn test_cow_is_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<CowBlockDevice<OneDevice>>();
+    }
+
+    #[test]
+    fn test_cow_read_falls_through() {
+        let cow = CowBlockDevice::new(OneDevice::new(7, 10));
+        assert_eq!(7, cow.block_size());
+        let mut data = vec![0_u8; 7];
+        cow.read_block(3, &mut data).unwrap();
+        assert_eq!(vec![1_u8; 7], data);
+    }
+
+    #[test]
+    fn test_cow_write_discard() {
+        let mut cow = CowBlockDevice::new(OneDevice::new(7, 10));
+        cow.write_block(5, &[2_u8; 7]).unwrap();
+        cow.write_block(2, &[3_u8; 7]).unwrap();
+        assert_eq!(Err(Error::NoSuchBlock), cow.write_block(10, &[3_u8; 7]));
+
+        let mut data = vec![0_u8; 7];
+        cow.read_block(5, &mut data).unwrap();
+        assert_eq!(vec![2_u8; 7], data);
+        cow.read_block(2, &mut data).unwrap();
+        assert_eq!(vec![3_u8; 7], data);
+        assert_eq!(vec![2, 5], cow.dirty_blocks().collect::<Vec<_>>());
+        assert_eq!(0, cow.inner.write_block_count.load(Ordering::SeqCst));
+
+        cow.discard();
+        cow.read_block(5, &mut data).unwrap();
+        assert_eq!(vec![1_u8; 7], data);
+        assert_eq!(0, cow.dirty_blocks().count());
+        assert_eq!(0, cow.inner.write_block_count.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_cow_commit() {
+        let mut cow = CowBlockDevice::new(OneDevice::new(512, 10));
+        for block in [1, 4, 1, 9] {
+            cow.write_block(block, &[2_u8; 512]).unwrap();
+        }
+        cow.commit().unwrap();
+        assert_eq!(3, cow.inner.write_block_count.load(Ordering::SeqCst));
+        assert_eq!(0, cow.dirty_blocks().count());
     }
 }
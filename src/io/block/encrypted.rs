@@ -0,0 +1,202 @@
+use alloc::vec::Vec;
+
+use crate::crypto::aes::BLOCK_SIZE;
+use crate::crypto::Xts;
+use crate::io::block::BlockDevice;
+use crate::io::{Error, Result};
+
+/// A [`BlockDevice`] that encrypts every block with AES in XTS mode before
+/// writing it to the inner device, and decrypts it after reading it.
+///
+/// The block number is used as the tweak, so every block is encrypted
+/// differently, and blocks can be read and written independently. There is
+/// no authentication: tampering with the inner device isn't detected, but
+/// results in garbage data. Put this device on top of an
+/// [`crate::io::block::integrity::IntegrityDevice`] to detect corruption.
+///
+/// ```
+/// # use kstd::io::block::BlockDevice;
+/// # use kstd::io::block::encrypted::EncryptedDevice;
+/// # use kstd::io::block::memory::MemoryBlockDevice;
+/// let key = [0x42_u8; 32];
+/// let mut device = EncryptedDevice::new(MemoryBlockDevice::new(512, 8), &key).unwrap();
+/// device.write_block(1, &[1_u8; 512]).unwrap();
+///
+/// let mut data = [0_u8; 512];
+/// device.read_block(1, &mut data).unwrap();
+/// assert_eq!([1_u8; 512], data);
+/// assert_ne!([1_u8; 512], device.get_ref().get_ref()[512..1024]);
+/// ```
+pub struct EncryptedDevice<D>
+where
+    D: BlockDevice,
+{
+    inner: D,
+    xts: Xts,
+}
+
+impl<D> EncryptedDevice<D>
+where
+    D: BlockDevice,
+{
+    /// Creates an encrypted device with the given key, see [`Xts::new`].
+    /// Fails with [`Error::InvalidArgument`] if the key has the wrong length,
+    /// or the block size is zero or not a multiple of the AES block size.
+    pub fn new(inner: D, key: &[u8]) -> Result<Self> {
+        let block_size = inner.block_size();
+        if block_size == 0 || !block_size.is_multiple_of(BLOCK_SIZE) {
+            return Err(Error::InvalidArgument);
+        }
+        Ok(Self {
+            inner,
+            xts: Xts::new(key)?,
+        })
+    }
+
+    pub fn get_ref(&self) -> &D {
+        &self.inner
+    }
+
+    /// Returns the inner device, which holds the encrypted data.
+    pub fn get_mut(&mut self) -> &mut D {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    fn decrypt(&self, start: u64, data: &mut [u8]) -> Result<()> {
+        let block_size = self.inner.block_size();
+        for (i, block) in data.chunks_exact_mut(block_size).enumerate() {
+            self.xts.decrypt_sector(start + i as u64, block)?;
+        }
+        Ok(())
+    }
+
+    fn encrypt(&self, start: u64, data: &[u8]) -> Result<Vec<u8>> {
+        let block_size = self.inner.block_size();
+        let mut encrypted = data.to_vec();
+        for (i, block) in encrypted.chunks_exact_mut(block_size).enumerate() {
+            self.xts.encrypt_sector(start + i as u64, block)?;
+        }
+        Ok(encrypted)
+    }
+}
+
+impl<D> BlockDevice for EncryptedDevice<D>
+where
+    D: BlockDevice,
+{
+    fn block_size(&self) -> usize {
+        self.inner.block_size()
+    }
+
+    fn block_count(&self) -> usize {
+        self.inner.block_count()
+    }
+
+    fn read_block(&self, block: u64, buf: &mut dyn AsMut<[u8]>) -> Result<usize> {
+        self.read_blocks(block, 1, buf)
+    }
+
+    fn write_block(&mut self, block: u64, buf: &dyn AsRef<[u8]>) -> Result<usize> {
+        self.write_blocks(block, 1, buf)
+    }
+
+    fn read_blocks(&self, start: u64, count: usize, buf: &mut dyn AsMut<[u8]>) -> Result<usize> {
+        let buffer = buf.as_mut();
+        let len = count * self.block_size();
+        if buffer.len() < len {
+            return Err(Error::BufferTooSmall);
+        }
+        let _ = self
+            .inner
+            .read_blocks(start, count, &mut &mut buffer[..len])?;
+        self.decrypt(start, &mut buffer[..len])?;
+        Ok(len)
+    }
+
+    fn write_blocks(&mut self, start: u64, count: usize, buf: &dyn AsRef<[u8]>) -> Result<usize> {
+        let buffer = buf.as_ref();
+        let len = count * self.block_size();
+        if buffer.len() < len {
+            return Err(Error::BufferTooSmall);
+        }
+        let encrypted = self.encrypt(start, &buffer[..len])?;
+        self.inner.write_blocks(start, count, &encrypted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use crate::crypto::Xts;
+    use crate::io::block::encrypted::EncryptedDevice;
+    use crate::io::block::memory::MemoryBlockDevice;
+    use crate::io::block::one::OneDevice;
+    use crate::io::block::BlockDevice;
+    use crate::io::{Error, ReadAt, WriteAt};
+
+    const KEY: [u8; 64] = [0x5A; 64];
+
+    #[test]
+    fn test_encrypted_roundtrip() {
+        let mut device = EncryptedDevice::new(MemoryBlockDevice::new(32, 8), &KEY).unwrap();
+        let data: Vec<u8> = (0..200).map(|i| i as u8).collect();
+        device.write_at(7, &data).unwrap();
+        let mut read = vec![0_u8; 200];
+        assert_eq!(Ok(200), device.read_at(7, &mut read));
+        assert_eq!(data, read);
+
+        // the same key decrypts the data after reopening
+        let device = EncryptedDevice::new(device.into_inner(), &KEY).unwrap();
+        let mut read = vec![0_u8; 200];
+        device.read_at(7, &mut read).unwrap();
+        assert_eq!(data, read);
+    }
+
+    #[test]
+    fn test_encrypted_layout() {
+        let mut device = EncryptedDevice::new(MemoryBlockDevice::new(32, 4), &KEY).unwrap();
+        device.write_blocks(1, 2, &[9_u8; 64]).unwrap();
+
+        // every block is a sector of its own, tweaked with its block number
+        let xts = Xts::new(&KEY).unwrap();
+        let image = device.into_inner().into_inner();
+        for block in 1..3 {
+            let mut expected = [9_u8; 32];
+            xts.encrypt_sector(block as u64, &mut expected).unwrap();
+            assert_eq!(expected, image[block * 32..(block + 1) * 32]);
+        }
+        assert_eq!([0_u8; 32], image[..32]);
+    }
+
+    #[test]
+    fn test_encrypted_wrong_key() {
+        let mut device = EncryptedDevice::new(MemoryBlockDevice::new(32, 4), &KEY).unwrap();
+        device.write_block(0, &[1_u8; 32]).unwrap();
+        let device = EncryptedDevice::new(device.into_inner(), &[0xA5; 64]).unwrap();
+        let mut data = [0_u8; 32];
+        device.read_block(0, &mut data).unwrap();
+        assert_ne!([1_u8; 32], data);
+    }
+
+    #[test]
+    fn test_encrypted_invalid() {
+        assert!(matches!(
+            EncryptedDevice::new(MemoryBlockDevice::new(24, 4), &KEY),
+            Err(Error::InvalidArgument)
+        ));
+        assert!(matches!(
+            EncryptedDevice::new(MemoryBlockDevice::new(32, 4), &KEY[..48]),
+            Err(Error::InvalidArgument)
+        ));
+        assert!(matches!(
+            EncryptedDevice::new(OneDevice::new(0, 4), &KEY),
+            Err(Error::InvalidArgument)
+        ));
+    }
+}
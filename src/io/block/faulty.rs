@@ -0,0 +1,382 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use crate::io::block::BlockDevice;
+use crate::io::{Error, Result};
+
+/// The seed that [`FaultyDevice::new`] uses.
+const DEFAULT_SEED: u64 = 0x2545_F491_4F6C_DD1D;
+
+/// The kind of operation that a fault applies to.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Operation {
+    Read,
+    Write,
+}
+
+/// Decides which operations a fault is injected into.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Trigger {
+    /// The operation with the given index, counting from zero. Every call to
+    /// [`BlockDevice::read_block`] or [`BlockDevice::write_block`] counts as one
+    /// operation, and multi-block transfers count once per block.
+    Nth(usize),
+    /// Every operation on the given block.
+    Block(u64),
+    /// Every operation with a probability of one in the given number,
+    /// decided by the seeded random number generator of the device.
+    Random { one_in: u32 },
+}
+
+/// What happens to an operation that a fault is injected into.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Fault {
+    /// The operation fails with the given error, without reaching the device.
+    Fail(Error),
+    /// Only the given number of bytes are read. Has no effect on writes.
+    ShortRead(usize),
+    /// A single bit at a random position of the block is flipped, either in
+    /// the data that is read or in the data that is written.
+    Corrupt,
+    /// The write reports success, but never reaches the device.
+    /// Has no effect on reads.
+    DropWrite,
+}
+
+struct Rule {
+    operation: Operation,
+    trigger: Trigger,
+    fault: Fault,
+}
+
+/// A [`BlockDevice`] for tests, that injects faults into the operations on the
+/// inner device, so that error handling code can be exercised.
+///
+/// Faults are added with [`FaultyDevice::inject`]. If multiple faults apply to
+/// an operation, only the one that was injected first takes effect. Random
+/// decisions are made by a generator with a fixed seed, so a test that performs
+/// the same operations always sees the same faults. Use [`FaultyDevice::with_seed`]
+/// to try different sequences.
+///
+/// ```
+/// # use kstd::io::block::BlockDevice;
+/// # use kstd::io::block::faulty::{Fault, FaultyDevice, Operation, Trigger};
+/// # use kstd::io::block::memory::MemoryBlockDevice;
+/// # use kstd::io::Error;
+/// let mut device = FaultyDevice::new(MemoryBlockDevice::new(512, 8));
+/// device.inject(Operation::Write, Trigger::Nth(1), Fault::Fail(Error::WriteError));
+///
+/// assert!(device.write_block(0, &[1_u8; 512]).is_ok());
+/// assert_eq!(Err(Error::WriteError), device.write_block(1, &[1_u8; 512]));
+/// assert!(device.write_block(1, &[1_u8; 512]).is_ok());
+/// ```
+pub struct FaultyDevice<D>
+where
+    D: BlockDevice,
+{
+    inner: D,
+    rules: Vec<Rule>,
+    reads: AtomicUsize,
+    writes: AtomicUsize,
+    injected: AtomicUsize,
+    /// The state of the xorshift random number generator.
+    state: AtomicU64,
+}
+
+impl<D> FaultyDevice<D>
+where
+    D: BlockDevice,
+{
+    pub fn new(inner: D) -> Self {
+        Self::with_seed(inner, DEFAULT_SEED)
+    }
+
+    pub fn with_seed(inner: D, seed: u64) -> Self {
+        Self {
+            inner,
+            rules: Vec::new(),
+            reads: AtomicUsize::default(),
+            writes: AtomicUsize::default(),
+            injected: AtomicUsize::default(),
+            // xorshift never leaves zero
+            state: AtomicU64::new(seed.max(1)),
+        }
+    }
+
+    pub fn get_ref(&self) -> &D {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut D {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    /// Injects the given fault into all operations of the given kind that
+    /// match the trigger.
+    pub fn inject(&mut self, operation: Operation, trigger: Trigger, fault: Fault) {
+        self.rules.push(Rule {
+            operation,
+            trigger,
+            fault,
+        });
+    }
+
+    /// Removes all faults. The operation counters are not reset.
+    pub fn clear(&mut self) {
+        self.rules.clear();
+    }
+
+    /// The number of reads that were attempted on this device.
+    pub fn reads(&self) -> usize {
+        self.reads.load(Ordering::Relaxed)
+    }
+
+    /// The number of writes that were attempted on this device.
+    pub fn writes(&self) -> usize {
+        self.writes.load(Ordering::Relaxed)
+    }
+
+    /// The number of operations that a fault was injected into.
+    pub fn injected(&self) -> usize {
+        self.injected.load(Ordering::Relaxed)
+    }
+
+    fn next_random(&self) -> u64 {
+        fn xorshift(mut x: u64) -> u64 {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            x
+        }
+
+        // a single update, so that concurrent callers never get the same value
+        let previous = self
+            .state
+            .try_update(Ordering::Relaxed, Ordering::Relaxed, |x| Some(xorshift(x)))
+            .unwrap();
+        xorshift(previous)
+    }
+
+    /// Returns the fault for the operation with the given index on the given block.
+    fn fault(&self, operation: Operation, index: usize, block: u64) -> Option<Fault> {
+        let fault = self
+            .rules
+            .iter()
+            .filter(|rule| rule.operation == operation)
+            .find(|rule| match rule.trigger {
+                Trigger::Nth(n) => n == index,
+                Trigger::Block(b) => b == block,
+                Trigger::Random { one_in } => {
+                    one_in > 0 && self.next_random().is_multiple_of(one_in as u64)
+                }
+            })
+            .map(|rule| rule.fault);
+        if fault.is_some() {
+            self.injected.fetch_add(1, Ordering::Relaxed);
+        }
+        fault
+    }
+
+    fn corrupt(&self, data: &mut [u8]) {
+        if data.is_empty() {
+            return;
+        }
+        let bit = (self.next_random() % (data.len() as u64 * 8)) as usize;
+        data[bit / 8] ^= 1 << (bit % 8);
+    }
+}
+
+impl<D> BlockDevice for FaultyDevice<D>
+where
+    D: BlockDevice,
+{
+    fn block_size(&self) -> usize {
+        self.inner.block_size()
+    }
+
+    fn block_count(&self) -> usize {
+        self.inner.block_count()
+    }
+
+    fn read_block(&self, block: u64, buf: &mut dyn AsMut<[u8]>) -> Result<usize> {
+        let index = self.reads.fetch_add(1, Ordering::Relaxed);
+        let buffer = buf.as_mut();
+        let block_size = self.block_size();
+        if buffer.len() < block_size {
+            return Err(Error::BufferTooSmall);
+        }
+
+        match self.fault(Operation::Read, index, block) {
+            Some(Fault::Fail(e)) => Err(e),
+            Some(Fault::ShortRead(len)) => {
+                let len = len.min(block_size);
+                let mut data = vec![0_u8; block_size];
+                let _ = self.inner.read_block(block, &mut data)?;
+                buffer[..len].copy_from_slice(&data[..len]);
+                Ok(len)
+            }
+            Some(Fault::Corrupt) => {
+                let len = self
+                    .inner
+                    .read_block(block, &mut &mut buffer[..block_size])?;
+                self.corrupt(&mut buffer[..block_size]);
+                Ok(len)
+            }
+            Some(Fault::DropWrite) | None => self.inner.read_block(block, buf),
+        }
+    }
+
+    fn write_block(&mut self, block: u64, buf: &dyn AsRef<[u8]>) -> Result<usize> {
+        let index = self.writes.fetch_add(1, Ordering::Relaxed);
+        let buffer = buf.as_ref();
+        let block_size = self.block_size();
+        if buffer.len() < block_size {
+            return Err(Error::BufferTooSmall);
+        }
+
+        match self.fault(Operation::Write, index, block) {
+            Some(Fault::Fail(e)) => Err(e),
+            Some(Fault::DropWrite) => Ok(block_size),
+            Some(Fault::Corrupt) => {
+                let mut data = buffer[..block_size].to_vec();
+                self.corrupt(&mut data);
+                self.inner.write_block(block, &data)
+            }
+            Some(Fault::ShortRead(_)) | None => self.inner.write_block(block, buf),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use crate::io::block::faulty::{Fault, FaultyDevice, Operation, Trigger};
+    use crate::io::block::memory::MemoryBlockDevice;
+    use crate::io::block::one::OneDevice;
+    use crate::io::block::BlockDevice;
+    use crate::io::{Error, ReadAt};
+
+    #[test]
+    fn test_faulty_nth_and_block() {
+        let mut device = FaultyDevice::new(OneDevice::new(4, 8));
+        device.inject(
+            Operation::Read,
+            Trigger::Nth(2),
+            Fault::Fail(Error::IncoherentData),
+        );
+        device.inject(
+            Operation::Read,
+            Trigger::Block(5),
+            Fault::Fail(Error::NoSuchBlock),
+        );
+        let mut data = [0_u8; 4];
+        let results: Vec<_> = [0, 1, 2, 3, 5, 5]
+            .into_iter()
+            .map(|block| device.read_block(block, &mut data))
+            .collect();
+        assert_eq!(
+            vec![
+                Ok(4),
+                Ok(4),
+                Err(Error::IncoherentData),
+                Ok(4),
+                Err(Error::NoSuchBlock),
+                Err(Error::NoSuchBlock)
+            ],
+            results
+        );
+        assert_eq!(6, device.reads());
+        assert_eq!(3, device.injected());
+
+        device.clear();
+        assert_eq!(Ok(4), device.read_block(5, &mut data));
+    }
+
+    #[test]
+    fn test_faulty_multi_block_read() {
+        let mut device = FaultyDevice::new(OneDevice::new(4, 8));
+        device.inject(
+            Operation::Read,
+            Trigger::Block(3),
+            Fault::Fail(Error::IncoherentData),
+        );
+        let mut data = [0_u8; 16];
+        assert_eq!(Ok(8), device.read_blocks(1, 2, &mut data));
+        assert_eq!(
+            Err(Error::IncoherentData),
+            device.read_blocks(1, 4, &mut data)
+        );
+        assert_eq!(
+            Err(Error::IncoherentData),
+            device.read_at(13, &mut [0_u8; 1])
+        );
+    }
+
+    #[test]
+    fn test_faulty_short_read_and_corrupt() {
+        let mut device = FaultyDevice::new(OneDevice::new(4, 8));
+        device.inject(Operation::Read, Trigger::Block(1), Fault::ShortRead(3));
+        device.inject(Operation::Read, Trigger::Block(2), Fault::Corrupt);
+        let mut data = [0_u8; 4];
+        assert_eq!(Ok(3), device.read_block(1, &mut data));
+        assert_eq!([1, 1, 1, 0], data);
+
+        let mut data = [0_u8; 4];
+        assert_eq!(Ok(4), device.read_block(2, &mut data));
+        let flipped: u32 = data.iter().map(|b| (b ^ 1).count_ones()).sum();
+        assert_eq!(1, flipped);
+    }
+
+    #[test]
+    fn test_faulty_drop_and_corrupt_writes() {
+        let mut device = FaultyDevice::new(MemoryBlockDevice::new(4, 4));
+        device.inject(Operation::Write, Trigger::Block(0), Fault::DropWrite);
+        device.inject(Operation::Write, Trigger::Block(1), Fault::Corrupt);
+        assert_eq!(Ok(4), device.write_block(0, &[7_u8; 4]));
+        assert_eq!(Ok(4), device.write_block(1, &[7_u8; 4]));
+        assert_eq!(Ok(4), device.write_block(2, &[7_u8; 4]));
+
+        let image = device.into_inner().into_inner();
+        assert_eq!([0_u8; 4], image[0..4]);
+        assert_ne!([7_u8; 4], image[4..8]);
+        assert_eq!([7_u8; 4], image[8..12]);
+    }
+
+    #[test]
+    fn test_faulty_corrupt_empty_block() {
+        let mut device = FaultyDevice::new(OneDevice::new(0, 4));
+        device.inject(Operation::Read, Trigger::Nth(0), Fault::Corrupt);
+        device.inject(Operation::Write, Trigger::Nth(0), Fault::Corrupt);
+        assert_eq!(Ok(0), device.read_block(0, &mut []));
+        assert_eq!(Ok(0), device.write_block(0, &[]));
+        assert_eq!(2, device.injected());
+    }
+
+    #[test]
+    fn test_faulty_random_is_deterministic() {
+        fn failures(seed: u64) -> Vec<usize> {
+            let mut device = FaultyDevice::with_seed(OneDevice::new(4, 64), seed);
+            device.inject(
+                Operation::Write,
+                Trigger::Random { one_in: 4 },
+                Fault::Fail(Error::WriteError),
+            );
+            (0..64)
+                .filter(|&block| device.write_block(block, &[0_u8; 4]).is_err())
+                .map(|block| block as usize)
+                .collect()
+        }
+
+        let first = failures(42);
+        assert!(!first.is_empty() && first.len() < 64);
+        assert_eq!(first, failures(42));
+        assert_ne!(first, failures(43));
+    }
+}
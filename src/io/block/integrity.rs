@@ -0,0 +1,371 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::checksum::crc32c;
+use crate::io::block::BlockDevice;
+use crate::io::{Error, Result};
+
+const MAGIC: &[u8; 8] = b"KSTDCRCC";
+const VERSION: u32 = 1;
+/// The size of the header fields in the first block.
+const HEADER_SIZE: usize = 12;
+/// The size of a stored checksum.
+const CHECKSUM_SIZE: usize = 4;
+
+/// A [`BlockDevice`] that stores a CRC-32C for every block, and verifies it on
+/// every read, so that silent corruption on the inner device is reported as
+/// [`Error::IncoherentData`] instead of being returned as data.
+///
+/// The first block of the inner device holds a header, and the checksums occupy
+/// its last blocks. Neither is accessible through this device. The checksums
+/// are loaded into memory when the device is opened, and written through on
+/// every write. The data block is written before its checksum, so a write that
+/// is interrupted leaves a block that fails verification, rather than one with
+/// unnoticed stale data. Combine this device with a
+/// [`crate::io::block::journal::JournalDevice`] on top if writes need to be
+/// crash consistent.
+///
+/// ```
+/// # use kstd::io::block::BlockDevice;
+/// # use kstd::io::block::integrity::IntegrityDevice;
+/// # use kstd::io::block::memory::MemoryBlockDevice;
+/// # use kstd::io::Error;
+/// let mut device = IntegrityDevice::format(MemoryBlockDevice::new(512, 8)).unwrap();
+/// assert_eq!(6, device.block_count());
+/// device.write_block(2, &[1_u8; 512]).unwrap();
+///
+/// // corrupt the block behind the back of the integrity layer, data blocks
+/// // start after the header block
+/// device.get_mut().write_block(3, &[3_u8; 512]).unwrap();
+/// assert_eq!(Err(Error::IncoherentData), device.read_block(2, &mut [0_u8; 512]));
+/// ```
+pub struct IntegrityDevice<D>
+where
+    D: BlockDevice,
+{
+    inner: D,
+    block_size: usize,
+    /// The checksum of every data block, which is also the number of
+    /// blocks that are accessible through this device.
+    checksums: Vec<u32>,
+}
+
+impl<D> IntegrityDevice<D>
+where
+    D: BlockDevice,
+{
+    /// Computes and stores the checksums of the current contents of the given
+    /// device. Every data block is read once. The header is written last, so
+    /// that an interrupted format isn't mistaken for a valid device.
+    ///
+    /// Fails with [`Error::InvalidArgument`] if the block size of the inner
+    /// device is smaller than 12 bytes, or if it has less than 3 blocks.
+    pub fn format(inner: D) -> Result<Self> {
+        let mut device = Self::new(inner)?;
+        let mut data = vec![0_u8; device.block_size];
+        for num in 0..device.checksums.len() {
+            let _ = device.inner.read_block(1 + num as u64, &mut data)?;
+            device.checksums[num] = crc32c(&data);
+        }
+        for index in 0..device.checksum_blocks() {
+            device.write_checksum_block(index)?;
+        }
+
+        let mut header = vec![0_u8; device.block_size];
+        header[0..8].copy_from_slice(MAGIC);
+        header[8..12].copy_from_slice(&VERSION.to_le_bytes());
+        let _ = device.inner.write_block(0, &header)?;
+        Ok(device)
+    }
+
+    /// Opens a device that was set up with [`IntegrityDevice::format`], and
+    /// loads the stored checksums. No data blocks are verified, use
+    /// [`IntegrityDevice::scrub`] for that.
+    ///
+    /// Returns [`Error::InvalidMagicNumber`] if there is no such device, and
+    /// [`Error::IncoherentData`] if it was written by an unsupported version.
+    pub fn open(inner: D) -> Result<Self> {
+        let mut header = vec![0_u8; inner.block_size().max(HEADER_SIZE)];
+        let _ = inner.read_block(0, &mut header)?;
+        if &header[0..8] != MAGIC {
+            return Err(Error::InvalidMagicNumber);
+        }
+        if u32::from_le_bytes(header[8..12].try_into().unwrap()) != VERSION {
+            return Err(Error::IncoherentData);
+        }
+
+        let mut device = Self::new(inner).map_err(|_| Error::IncoherentData)?;
+        let per_block = device.checksums_per_block();
+        let first = 1 + device.checksums.len() as u64;
+        let mut data = vec![0_u8; device.block_size];
+        for index in 0..device.checksum_blocks() {
+            let _ = device.inner.read_block(first + index as u64, &mut data)?;
+            let start = index * per_block;
+            let end = (start + per_block).min(device.checksums.len());
+            for (checksum, bytes) in device.checksums[start..end]
+                .iter_mut()
+                .zip(data.as_chunks::<CHECKSUM_SIZE>().0)
+            {
+                *checksum = u32::from_le_bytes(*bytes);
+            }
+        }
+        Ok(device)
+    }
+
+    fn new(inner: D) -> Result<Self> {
+        let block_size = inner.block_size();
+        let block_count = inner.block_count();
+        let per_block = block_size / CHECKSUM_SIZE;
+        if block_size < HEADER_SIZE || block_count < 3 {
+            return Err(Error::InvalidArgument);
+        }
+        // after the header, every group of `per_block` data blocks needs one
+        // block of checksums
+        let data_blocks = (block_count - 1) * per_block / (per_block + 1);
+        Ok(Self {
+            inner,
+            block_size,
+            checksums: vec![0; data_blocks],
+        })
+    }
+
+    pub fn get_ref(&self) -> &D {
+        &self.inner
+    }
+
+    /// Returns the inner device. Writes to the data blocks of the inner device
+    /// bypass the checksums, and make the affected blocks fail verification.
+    pub fn get_mut(&mut self) -> &mut D {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    /// Reads every block and returns the numbers of those whose checksum
+    /// doesn't match.
+    pub fn scrub(&self) -> Result<Vec<u64>> {
+        let mut data = vec![0_u8; self.block_size];
+        let mut corrupted = Vec::new();
+        for num in 0..self.checksums.len() as u64 {
+            match self.read_block(num, &mut data) {
+                Ok(_) => {}
+                Err(Error::IncoherentData) => corrupted.push(num),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(corrupted)
+    }
+
+    fn checksums_per_block(&self) -> usize {
+        self.block_size / CHECKSUM_SIZE
+    }
+
+    fn checksum_blocks(&self) -> usize {
+        self.checksums.len().div_ceil(self.checksums_per_block())
+    }
+
+    /// Writes the block of checksums with the given index to the inner device.
+    fn write_checksum_block(&mut self, index: usize) -> Result<()> {
+        let per_block = self.checksums_per_block();
+        let start = index * per_block;
+        let end = (start + per_block).min(self.checksums.len());
+        let mut data = vec![0_u8; self.block_size];
+        for (bytes, checksum) in data
+            .as_chunks_mut::<CHECKSUM_SIZE>()
+            .0
+            .iter_mut()
+            .zip(&self.checksums[start..end])
+        {
+            *bytes = checksum.to_le_bytes();
+        }
+        let num = (1 + self.checksums.len() + index) as u64;
+        let _ = self.inner.write_block(num, &data)?;
+        Ok(())
+    }
+
+    /// Verifies the given blocks, that were read starting at the given block number.
+    fn verify(&self, start: u64, data: &[u8]) -> Result<()> {
+        for (i, block) in data.chunks_exact(self.block_size).enumerate() {
+            if crc32c(block) != self.checksums[start as usize + i] {
+                return Err(Error::IncoherentData);
+            }
+        }
+        Ok(())
+    }
+
+    /// Updates the checksums of the given blocks, that were written starting at
+    /// the given block number, and writes the affected checksum blocks.
+    fn update(&mut self, start: u64, data: &[u8]) -> Result<()> {
+        let count = data.len() / self.block_size;
+        for (i, block) in data.chunks_exact(self.block_size).enumerate() {
+            self.checksums[start as usize + i] = crc32c(block);
+        }
+        let per_block = self.checksums_per_block();
+        let first = start as usize / per_block;
+        let last = (start as usize + count - 1) / per_block;
+        for index in first..=last {
+            self.write_checksum_block(index)?;
+        }
+        Ok(())
+    }
+
+    fn check_range(&self, start: u64, count: usize, len: usize) -> Result<()> {
+        if start
+            .checked_add(count as u64)
+            .is_none_or(|end| end > self.checksums.len() as u64)
+        {
+            return Err(Error::NoSuchBlock);
+        }
+        if len < count * self.block_size {
+            return Err(Error::BufferTooSmall);
+        }
+        Ok(())
+    }
+}
+
+impl<D> BlockDevice for IntegrityDevice<D>
+where
+    D: BlockDevice,
+{
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn block_count(&self) -> usize {
+        self.checksums.len()
+    }
+
+    fn read_block(&self, block: u64, buf: &mut dyn AsMut<[u8]>) -> Result<usize> {
+        self.read_blocks(block, 1, buf)
+    }
+
+    fn write_block(&mut self, block: u64, buf: &dyn AsRef<[u8]>) -> Result<usize> {
+        self.write_blocks(block, 1, buf)
+    }
+
+    fn read_blocks(&self, start: u64, count: usize, buf: &mut dyn AsMut<[u8]>) -> Result<usize> {
+        let buffer = buf.as_mut();
+        self.check_range(start, count, buffer.len())?;
+        if count == 0 {
+            return Ok(0);
+        }
+        let len = count * self.block_size;
+        let _ = self
+            .inner
+            .read_blocks(1 + start, count, &mut &mut buffer[..len])?;
+        self.verify(start, &buffer[..len])?;
+        Ok(len)
+    }
+
+    fn write_blocks(&mut self, start: u64, count: usize, buf: &dyn AsRef<[u8]>) -> Result<usize> {
+        let buffer = buf.as_ref();
+        self.check_range(start, count, buffer.len())?;
+        if count == 0 {
+            return Ok(0);
+        }
+        let len = count * self.block_size;
+        let _ = self.inner.write_blocks(1 + start, count, &&buffer[..len])?;
+        self.update(start, &buffer[..len])?;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use crate::io::block::faulty::{Fault, FaultyDevice, Operation, Trigger};
+    use crate::io::block::integrity::IntegrityDevice;
+    use crate::io::block::memory::MemoryBlockDevice;
+    use crate::io::block::BlockDevice;
+    use crate::io::{Error, ReadAt, WriteAt};
+
+    #[test]
+    fn test_integrity_layout() {
+        // 4 checksums fit into a block, so 16 blocks hold a header, 12 data blocks
+        // and 3 checksum blocks
+        let device = IntegrityDevice::format(MemoryBlockDevice::new(16, 16)).unwrap();
+        assert_eq!(12, device.block_count());
+        assert_eq!(16, device.block_size());
+        assert_eq!(
+            Err(Error::InvalidArgument),
+            IntegrityDevice::format(MemoryBlockDevice::new(8, 16)).map(|_| ())
+        );
+        assert_eq!(
+            Err(Error::InvalidArgument),
+            IntegrityDevice::format(MemoryBlockDevice::new(16, 2)).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn test_integrity_read_write() {
+        let mut device = IntegrityDevice::format(MemoryBlockDevice::new(16, 16)).unwrap();
+        let data: Vec<u8> = (0..100).collect();
+        device.write_at(10, &data).unwrap();
+        let mut read = vec![0_u8; 100];
+        assert_eq!(Ok(100), device.read_at(10, &mut read));
+        assert_eq!(data, read);
+        assert_eq!(
+            Err(Error::NoSuchBlock),
+            device.read_block(12, &mut [0_u8; 16])
+        );
+    }
+
+    #[test]
+    fn test_integrity_detects_corruption() {
+        let mut device = IntegrityDevice::format(MemoryBlockDevice::new(16, 16)).unwrap();
+        device.write_block(5, &[1_u8; 16]).unwrap();
+        // the inner device has the header in its first block
+        device.get_mut().write_block(6, &[1_u8; 16]).unwrap();
+        device.get_mut().write_block(8, &[2_u8; 16]).unwrap();
+        device.get_mut().write_block(10, &[3_u8; 16]).unwrap();
+        assert_eq!(Ok(vec![7, 9]), device.scrub());
+        assert_eq!(
+            Err(Error::IncoherentData),
+            device.read_blocks(6, 2, &mut [0_u8; 32])
+        );
+
+        // rewriting a block repairs it
+        device.write_block(7, &[2_u8; 16]).unwrap();
+        assert_eq!(Ok(vec![9]), device.scrub());
+    }
+
+    #[test]
+    fn test_integrity_detects_faulty_reads() {
+        let device = IntegrityDevice::format(MemoryBlockDevice::new(16, 16)).unwrap();
+        let mut faulty = FaultyDevice::new(device.into_inner());
+        faulty.inject(Operation::Read, Trigger::Block(4), Fault::Corrupt);
+        let device = IntegrityDevice::open(faulty).unwrap();
+        assert_eq!(Ok(16), device.read_block(2, &mut [0_u8; 16]));
+        assert_eq!(
+            Err(Error::IncoherentData),
+            device.read_block(3, &mut [0_u8; 16])
+        );
+    }
+
+    #[test]
+    fn test_integrity_open() {
+        let mut device = IntegrityDevice::format(MemoryBlockDevice::new(16, 16)).unwrap();
+        device.write_at(0, &[7_u8; 192]).unwrap();
+
+        let device = IntegrityDevice::open(device.into_inner()).unwrap();
+        assert_eq!(Ok(Vec::new()), device.scrub());
+        let mut data = [0_u8; 192];
+        assert_eq!(Ok(192), device.read_at(0, &mut data));
+        assert_eq!([7_u8; 192], data);
+
+        assert_eq!(
+            Err(Error::InvalidMagicNumber),
+            IntegrityDevice::open(MemoryBlockDevice::new(16, 16)).map(|_| ())
+        );
+        let mut inner = device.into_inner();
+        inner.write_at(8, &2_u32.to_le_bytes()).unwrap();
+        assert_eq!(
+            Err(Error::IncoherentData),
+            IntegrityDevice::open(inner).map(|_| ())
+        );
+    }
+}
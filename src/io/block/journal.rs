@@ -0,0 +1,541 @@
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::checksum::Crc32;
+use crate::io::block::BlockDevice;
+use crate::io::{Error, Result};
+
+const SUPERBLOCK_MAGIC: &[u8; 8] = b"KSTDJRNL";
+const DESCRIPTOR_MAGIC: &[u8; 8] = b"KJDESCR\0";
+const COMMIT_MAGIC: &[u8; 8] = b"KJCOMMIT";
+const VERSION: u32 = 1;
+/// The size of the descriptor fields before the list of block numbers.
+const DESCRIPTOR_HEADER_SIZE: usize = 20;
+/// The smallest block size that can hold the superblock, the commit record,
+/// and a descriptor with at least one block number.
+const MIN_BLOCK_SIZE: usize = 32;
+
+/// The blocks of a transaction by block number.
+type Blocks = BTreeMap<u64, Vec<u8>>;
+
+/// A [`BlockDevice`] that makes groups of writes crash consistent by writing
+/// them to a journal before writing them to their actual location.
+///
+/// The journal occupies the last blocks of the inner device, which are not
+/// accessible through this device. Its first block is a superblock that
+/// identifies the journal. A transaction is written to the journal as a
+/// descriptor block listing the target block numbers, followed by the data
+/// blocks and a commit record with a checksum over the descriptor and the data.
+/// Only once the commit record is written, the data blocks are written to their
+/// actual location (checkpointing).
+///
+/// When the device is opened with [`JournalDevice::open`], a transaction with a
+/// valid commit record is replayed, since the checkpoint may have been interrupted.
+/// A transaction without a valid commit record is ignored, and none of its writes
+/// are visible. Replaying is idempotent, so it doesn't matter whether the
+/// transaction was checkpointed before or not.
+///
+/// Writes are grouped with [`JournalDevice::transaction`]. A single
+/// [`BlockDevice::write_block`] on this device is a transaction of its own.
+/// Every transaction is checkpointed before the call that commits it returns.
+/// If the checkpoint fails, it is retried before the next transaction is
+/// written to the journal.
+pub struct JournalDevice<D>
+where
+    D: BlockDevice,
+{
+    inner: D,
+    block_size: usize,
+    /// The first block of the journal on the inner device, which is
+    /// also the number of blocks that are accessible through this device.
+    journal_start: u64,
+    journal_blocks: usize,
+    /// The sequence number of the last transaction that was written.
+    sequence: u64,
+    /// The blocks of the last committed transaction, until its checkpoint has
+    /// completed. If the checkpoint fails, it is retried before the next
+    /// transaction overwrites the journal, and reads see these blocks meanwhile.
+    pending: Blocks,
+}
+
+impl<D> JournalDevice<D>
+where
+    D: BlockDevice,
+{
+    /// Creates an empty journal in the last `journal_blocks` blocks of the given
+    /// device. The journal needs at least four blocks, which allows transactions
+    /// of a single block.
+    pub fn format(inner: D, journal_blocks: usize) -> Result<Self> {
+        let mut device = Self::new(inner, journal_blocks)?;
+
+        let mut data = vec![0_u8; device.block_size];
+        data[0..8].copy_from_slice(SUPERBLOCK_MAGIC);
+        data[8..12].copy_from_slice(&VERSION.to_le_bytes());
+        data[12..16].copy_from_slice(&(journal_blocks as u32).to_le_bytes());
+        let _ = device.inner.write_block(device.journal_start, &data)?;
+        // invalidate any transaction that may be left in the journal region
+        data.fill(0);
+        let _ = device.inner.write_block(device.journal_start + 1, &data)?;
+        Ok(device)
+    }
+
+    /// Opens a journal that was created with [`JournalDevice::format`], and replays
+    /// the last transaction if it was committed.
+    ///
+    /// Returns [`Error::InvalidMagicNumber`] if there is no journal with the given
+    /// size, and [`Error::IncoherentData`] if the journal is from a device with a
+    /// different size.
+    pub fn open(inner: D, journal_blocks: usize) -> Result<Self> {
+        let mut device = Self::new(inner, journal_blocks)?;
+
+        let mut data = vec![0_u8; device.block_size];
+        let _ = device.inner.read_block(device.journal_start, &mut data)?;
+        if &data[0..8] != SUPERBLOCK_MAGIC {
+            return Err(Error::InvalidMagicNumber);
+        }
+        if u32_at(&data, 8) != VERSION || u32_at(&data, 12) as usize != journal_blocks {
+            return Err(Error::IncoherentData);
+        }
+
+        if let Some((sequence, blocks)) = device.read_transaction()? {
+            device.sequence = sequence;
+            device.pending = blocks;
+            device.checkpoint()?;
+        }
+        Ok(device)
+    }
+
+    fn new(inner: D, journal_blocks: usize) -> Result<Self> {
+        let block_size = inner.block_size();
+        let block_count = inner.block_count();
+        if block_size < MIN_BLOCK_SIZE || journal_blocks < 4 || journal_blocks > block_count {
+            return Err(Error::InvalidArgument);
+        }
+        Ok(Self {
+            inner,
+            block_size,
+            journal_start: (block_count - journal_blocks) as u64,
+            journal_blocks,
+            sequence: 0,
+            pending: BTreeMap::new(),
+        })
+    }
+
+    pub fn get_ref(&self) -> &D {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut D {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    /// The maximum number of distinct blocks that a single transaction can write.
+    pub fn max_transaction_blocks(&self) -> usize {
+        (self.journal_blocks - 3).min((self.block_size - DESCRIPTOR_HEADER_SIZE) / 8)
+    }
+
+    /// Starts a transaction. Writes to the transaction are only visible through
+    /// the transaction, until it is committed with [`Transaction::commit`].
+    /// Dropping the transaction without committing discards the writes.
+    pub fn transaction(&mut self) -> Transaction<'_, D> {
+        Transaction {
+            device: self,
+            blocks: BTreeMap::new(),
+        }
+    }
+
+    /// Writes the given blocks to the journal, and then to their actual location.
+    fn commit(&mut self, blocks: Blocks) -> Result<()> {
+        // the journal only holds a single transaction
+        self.checkpoint()?;
+        if blocks.is_empty() {
+            return Ok(());
+        }
+        let sequence = self.sequence + 1;
+
+        let mut descriptor = vec![0_u8; self.block_size];
+        descriptor[0..8].copy_from_slice(DESCRIPTOR_MAGIC);
+        descriptor[8..16].copy_from_slice(&sequence.to_le_bytes());
+        descriptor[16..20].copy_from_slice(&(blocks.len() as u32).to_le_bytes());
+        for (i, &num) in blocks.keys().enumerate() {
+            let offset = DESCRIPTOR_HEADER_SIZE + i * 8;
+            descriptor[offset..offset + 8].copy_from_slice(&num.to_le_bytes());
+        }
+
+        let mut crc = Crc32::new();
+        crc.update(&descriptor);
+        let _ = self
+            .inner
+            .write_block(self.journal_start + 1, &descriptor)?;
+        for (i, data) in blocks.values().enumerate() {
+            crc.update(data);
+            let _ = self
+                .inner
+                .write_block(self.journal_start + 2 + i as u64, data)?;
+        }
+
+        let mut commit = vec![0_u8; self.block_size];
+        commit[0..8].copy_from_slice(COMMIT_MAGIC);
+        commit[8..16].copy_from_slice(&sequence.to_le_bytes());
+        commit[16..20].copy_from_slice(&crc.finish().to_le_bytes());
+        let _ = self
+            .inner
+            .write_block(self.journal_start + 2 + blocks.len() as u64, &commit)?;
+        self.sequence = sequence;
+        self.pending = blocks;
+
+        self.checkpoint()
+    }
+
+    /// Writes the blocks of the last committed transaction to their actual location.
+    fn checkpoint(&mut self) -> Result<()> {
+        for (&num, data) in &self.pending {
+            let _ = self.inner.write_block(num, data)?;
+        }
+        self.pending.clear();
+        Ok(())
+    }
+
+    /// Reads the transaction in the journal, if there is one with a valid
+    /// commit record, and returns its sequence number and blocks.
+    fn read_transaction(&self) -> Result<Option<(u64, Blocks)>> {
+        let mut descriptor = vec![0_u8; self.block_size];
+        let _ = self
+            .inner
+            .read_block(self.journal_start + 1, &mut descriptor)?;
+        if &descriptor[0..8] != DESCRIPTOR_MAGIC {
+            return Ok(None);
+        }
+        let sequence = u64_at(&descriptor, 8);
+        let count = u32_at(&descriptor, 16) as usize;
+        if count == 0 || count > self.max_transaction_blocks() {
+            return Ok(None);
+        }
+
+        let mut crc = Crc32::new();
+        crc.update(&descriptor);
+        let mut blocks = BTreeMap::new();
+        for i in 0..count {
+            let num = u64_at(&descriptor, DESCRIPTOR_HEADER_SIZE + i * 8);
+            let mut data = vec![0_u8; self.block_size];
+            let _ = self
+                .inner
+                .read_block(self.journal_start + 2 + i as u64, &mut data)?;
+            crc.update(&data);
+            blocks.insert(num, data);
+        }
+
+        let mut commit = vec![0_u8; self.block_size];
+        let _ = self
+            .inner
+            .read_block(self.journal_start + 2 + count as u64, &mut commit)?;
+        if &commit[0..8] != COMMIT_MAGIC
+            || u64_at(&commit, 8) != sequence
+            || u32_at(&commit, 16) != crc.finish()
+        {
+            // the transaction was interrupted before it was committed
+            return Ok(None);
+        }
+        if blocks.len() != count || blocks.keys().any(|&num| num >= self.journal_start) {
+            return Err(Error::IncoherentData);
+        }
+        Ok(Some((sequence, blocks)))
+    }
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn u64_at(data: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+impl<D> BlockDevice for JournalDevice<D>
+where
+    D: BlockDevice,
+{
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn block_count(&self) -> usize {
+        self.journal_start as usize
+    }
+
+    fn read_block(&self, block: u64, buf: &mut dyn AsMut<[u8]>) -> Result<usize> {
+        if block >= self.journal_start {
+            return Err(Error::NoSuchBlock);
+        }
+        if let Some(data) = self.pending.get(&block) {
+            let buffer = buf.as_mut();
+            if buffer.len() < self.block_size {
+                return Err(Error::BufferTooSmall);
+            }
+            buffer[..self.block_size].copy_from_slice(data);
+            return Ok(self.block_size);
+        }
+        self.inner.read_block(block, buf)
+    }
+
+    fn write_block(&mut self, block: u64, buf: &dyn AsRef<[u8]>) -> Result<usize> {
+        let mut transaction = self.transaction();
+        let len = transaction.write_block(block, buf)?;
+        transaction.commit()?;
+        Ok(len)
+    }
+}
+
+/// A group of writes to a [`JournalDevice`] that is applied atomically,
+/// created with [`JournalDevice::transaction`].
+///
+/// Reads through the transaction see its own writes. The writes are kept in
+/// memory until [`Transaction::commit`] is called, so a transaction can write
+/// at most [`JournalDevice::max_transaction_blocks`] distinct blocks. Writing
+/// more blocks fails with [`Error::WriteError`].
+pub struct Transaction<'a, D>
+where
+    D: BlockDevice,
+{
+    device: &'a mut JournalDevice<D>,
+    blocks: Blocks,
+}
+
+impl<D> Transaction<'_, D>
+where
+    D: BlockDevice,
+{
+    /// Writes all blocks of this transaction to the journal, and then to
+    /// their actual location. If this fails, the transaction may or may not
+    /// have been committed, which is decided when the device is opened again.
+    pub fn commit(self) -> Result<()> {
+        self.device.commit(self.blocks)
+    }
+}
+
+impl<D> BlockDevice for Transaction<'_, D>
+where
+    D: BlockDevice,
+{
+    fn block_size(&self) -> usize {
+        self.device.block_size
+    }
+
+    fn block_count(&self) -> usize {
+        self.device.block_count()
+    }
+
+    fn read_block(&self, block: u64, buf: &mut dyn AsMut<[u8]>) -> Result<usize> {
+        let buffer = buf.as_mut();
+        let block_size = self.block_size();
+        if buffer.len() < block_size {
+            return Err(Error::BufferTooSmall);
+        }
+
+        match self.blocks.get(&block) {
+            Some(b) => {
+                buffer[0..block_size].copy_from_slice(b);
+                Ok(block_size)
+            }
+            None => self.device.read_block(block, buf),
+        }
+    }
+
+    fn write_block(&mut self, block: u64, buf: &dyn AsRef<[u8]>) -> Result<usize> {
+        let buffer = buf.as_ref();
+        let block_size = self.block_size();
+        if buffer.len() < block_size {
+            return Err(Error::BufferTooSmall);
+        }
+        if block >= self.block_count() as u64 {
+            return Err(Error::NoSuchBlock);
+        }
+
+        match self.blocks.get_mut(&block) {
+            Some(b) => b.copy_from_slice(&buffer[0..block_size]),
+            None => {
+                if self.blocks.len() >= self.device.max_transaction_blocks() {
+                    return Err(Error::WriteError);
+                }
+                self.blocks.insert(block, buffer[0..block_size].to_vec());
+            }
+        }
+        Ok(block_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::block::faulty::{Fault, FaultyDevice, Operation, Trigger};
+    use crate::io::block::journal::JournalDevice;
+    use crate::io::block::memory::MemoryBlockDevice;
+    use crate::io::block::BlockDevice;
+    use crate::io::{Error, Result};
+
+    /// A device that silently drops all writes after a cutoff, like a disk that
+    /// loses power in the middle of a transaction.
+    struct CrashDevice {
+        inner: MemoryBlockDevice,
+        writes_left: usize,
+    }
+
+    impl BlockDevice for CrashDevice {
+        fn block_size(&self) -> usize {
+            self.inner.block_size()
+        }
+
+        fn block_count(&self) -> usize {
+            self.inner.block_count()
+        }
+
+        fn read_block(&self, block: u64, buf: &mut dyn AsMut<[u8]>) -> Result<usize> {
+            self.inner.read_block(block, buf)
+        }
+
+        fn write_block(&mut self, block: u64, buf: &dyn AsRef<[u8]>) -> Result<usize> {
+            if self.writes_left == 0 {
+                return Ok(self.block_size());
+            }
+            self.writes_left -= 1;
+            self.inner.write_block(block, buf)
+        }
+    }
+
+    fn read(device: &impl BlockDevice, block: u64) -> [u8; 64] {
+        let mut data = [0_u8; 64];
+        device.read_block(block, &mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn test_journal_format_open() {
+        let device = JournalDevice::format(MemoryBlockDevice::new(64, 16), 6).unwrap();
+        assert_eq!(10, device.block_count());
+        assert_eq!(3, device.max_transaction_blocks());
+        assert_eq!(
+            Err(Error::NoSuchBlock),
+            device.read_block(10, &mut [0_u8; 64])
+        );
+
+        let device = JournalDevice::open(device.into_inner(), 6).unwrap();
+        assert_eq!(10, device.block_count());
+        assert!(matches!(
+            JournalDevice::open(device.into_inner(), 5),
+            Err(Error::InvalidMagicNumber)
+        ));
+        assert!(matches!(
+            JournalDevice::open(MemoryBlockDevice::new(64, 16), 6),
+            Err(Error::InvalidMagicNumber)
+        ));
+        assert!(matches!(
+            JournalDevice::format(MemoryBlockDevice::new(64, 16), 3),
+            Err(Error::InvalidArgument)
+        ));
+    }
+
+    #[test]
+    fn test_journal_transaction() {
+        let mut device = JournalDevice::format(MemoryBlockDevice::new(64, 16), 6).unwrap();
+        let mut transaction = device.transaction();
+        transaction.write_block(1, &[1_u8; 64]).unwrap();
+        transaction.write_block(2, &[2_u8; 64]).unwrap();
+        transaction.write_block(1, &[3_u8; 64]).unwrap();
+        assert_eq!([3_u8; 64], read(&transaction, 1));
+        transaction.write_block(3, &[4_u8; 64]).unwrap();
+        assert_eq!(
+            Err(Error::WriteError),
+            transaction.write_block(4, &[4_u8; 64])
+        );
+        transaction.commit().unwrap();
+        assert_eq!([3_u8; 64], read(&device, 1));
+        assert_eq!([2_u8; 64], read(&device, 2));
+        assert_eq!([4_u8; 64], read(&device, 3));
+
+        let mut transaction = device.transaction();
+        transaction.write_block(1, &[5_u8; 64]).unwrap();
+        drop(transaction);
+        assert_eq!([3_u8; 64], read(&device, 1));
+    }
+
+    #[test]
+    fn test_journal_crash_consistency() {
+        let mut image = MemoryBlockDevice::new(64, 16);
+        for block in 0..3 {
+            image.write_block(block, &[1_u8; 64]).unwrap();
+        }
+        let image = image.into_inner();
+
+        // descriptor, 3 data blocks, commit record, 3 checkpoint writes
+        for cutoff in 0..=8 {
+            let device = CrashDevice {
+                inner: MemoryBlockDevice::from_image(image.clone(), 64),
+                writes_left: usize::MAX,
+            };
+            let mut device = JournalDevice::format(device, 6).unwrap();
+            device.get_mut().writes_left = cutoff;
+            let mut transaction = device.transaction();
+            for block in 0..3 {
+                transaction.write_block(block, &[2_u8; 64]).unwrap();
+            }
+            transaction.commit().unwrap();
+
+            let device = JournalDevice::open(device.into_inner().inner, 6).unwrap();
+            let expected = if cutoff >= 5 { 2 } else { 1 };
+            for block in 0..3 {
+                assert_eq!([expected; 64], read(&device, block), "cutoff {}", cutoff);
+            }
+        }
+    }
+
+    #[test]
+    fn test_journal_retries_failed_checkpoint() {
+        let mut faulty = FaultyDevice::new(MemoryBlockDevice::new(64, 16));
+        // formatting writes 2 blocks, and the first transaction is checkpointed
+        // with the 6th write
+        faulty.inject(
+            Operation::Write,
+            Trigger::Nth(5),
+            Fault::Fail(Error::WriteError),
+        );
+        // the disk loses power while the second transaction is written
+        for n in 7..11 {
+            faulty.inject(Operation::Write, Trigger::Nth(n), Fault::DropWrite);
+        }
+        let mut device = JournalDevice::format(faulty, 6).unwrap();
+        assert_eq!(Err(Error::WriteError), device.write_block(0, &[2_u8; 64]));
+        // the transaction was committed, so it is visible
+        assert_eq!([2_u8; 64], read(&device, 0));
+
+        // the checkpoint is retried before the journal is overwritten
+        device.write_block(1, &[3_u8; 64]).unwrap();
+        let device = JournalDevice::open(device.into_inner().into_inner(), 6).unwrap();
+        assert_eq!([2_u8; 64], read(&device, 0));
+        assert_eq!([0_u8; 64], read(&device, 1));
+    }
+
+    #[test]
+    fn test_journal_ignores_older_commit() {
+        let mut device = JournalDevice::format(MemoryBlockDevice::new(64, 16), 6).unwrap();
+        device.write_block(0, &[1_u8; 64]).unwrap();
+        let inner = device.into_inner();
+        let mut device = JournalDevice::open(
+            CrashDevice {
+                inner,
+                writes_left: 2,
+            },
+            6,
+        )
+        .unwrap();
+        // the descriptor and data of the second transaction are written,
+        // but the commit record of the first one is still in the journal
+        device.write_block(0, &[2_u8; 64]).unwrap();
+
+        let device = JournalDevice::open(device.into_inner().inner, 6).unwrap();
+        assert_eq!([1_u8; 64], read(&device, 0));
+    }
+}
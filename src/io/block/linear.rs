@@ -0,0 +1,226 @@
+use alloc::vec::Vec;
+
+use crate::io::block::raid::common_block_size;
+use crate::io::block::BlockDevice;
+use crate::io::{Error, Result};
+
+/// A [`BlockDevice`] that concatenates its members into a single address space.
+///
+/// The blocks of the first member come first, followed by the blocks of the
+/// second member, and so on, so the device has as many blocks as all members
+/// together. Transfers that span multiple members are split at the member
+/// boundaries.
+///
+/// ```
+/// # use kstd::io::block::BlockDevice;
+/// # use kstd::io::block::linear::LinearDevice;
+/// # use kstd::io::block::memory::MemoryBlockDevice;
+/// let mut device = LinearDevice::new(vec![
+///     MemoryBlockDevice::new(512, 2),
+///     MemoryBlockDevice::new(512, 3),
+/// ])
+/// .unwrap();
+/// assert_eq!(5, device.block_count());
+/// device.write_block(2, &[1_u8; 512]).unwrap();
+///
+/// let members = device.into_inner();
+/// assert_eq!([1_u8; 512], members[1].get_ref()[..512]);
+/// ```
+pub struct LinearDevice<D>
+where
+    D: BlockDevice,
+{
+    members: Vec<D>,
+    /// The first block of every member, followed by the total block count.
+    starts: Vec<u64>,
+    block_size: usize,
+}
+
+impl<D> LinearDevice<D>
+where
+    D: BlockDevice,
+{
+    /// Creates a device from the given devices, which must all have the same
+    /// block size, otherwise this fails with [`Error::InvalidArgument`].
+    pub fn new(members: Vec<D>) -> Result<Self> {
+        let block_size = common_block_size(&members)?;
+        let mut starts = Vec::with_capacity(members.len() + 1);
+        let mut start = 0;
+        for member in &members {
+            starts.push(start);
+            start += member.block_count() as u64;
+        }
+        starts.push(start);
+        Ok(Self {
+            members,
+            starts,
+            block_size,
+        })
+    }
+
+    pub fn members(&self) -> &[D] {
+        &self.members
+    }
+
+    pub fn members_mut(&mut self) -> &mut [D] {
+        &mut self.members
+    }
+
+    pub fn into_inner(self) -> Vec<D> {
+        self.members
+    }
+
+    /// The first block of the member with the given index on this device.
+    pub fn start(&self, index: usize) -> u64 {
+        self.starts[index]
+    }
+
+    /// Returns the index of the member that holds the given block, the number
+    /// of the block on that member, and how many blocks of the member follow it.
+    fn locate(&self, block: u64) -> Result<(usize, u64, usize)> {
+        if block >= self.starts[self.members.len()] {
+            return Err(Error::NoSuchBlock);
+        }
+        // members without blocks share their start with the next member
+        let index = self.starts.partition_point(|&start| start <= block) - 1;
+        let offset = block - self.starts[index];
+        let remaining = (self.starts[index + 1] - block) as usize;
+        Ok((index, offset, remaining))
+    }
+
+    fn check_range(&self, start: u64, count: usize, len: usize) -> Result<()> {
+        if start
+            .checked_add(count as u64)
+            .is_none_or(|end| end > self.starts[self.members.len()])
+        {
+            return Err(Error::NoSuchBlock);
+        }
+        if len < count * self.block_size {
+            return Err(Error::BufferTooSmall);
+        }
+        Ok(())
+    }
+}
+
+impl<D> BlockDevice for LinearDevice<D>
+where
+    D: BlockDevice,
+{
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn block_count(&self) -> usize {
+        self.starts[self.members.len()] as usize
+    }
+
+    fn read_block(&self, block: u64, buf: &mut dyn AsMut<[u8]>) -> Result<usize> {
+        let (index, offset, _) = self.locate(block)?;
+        self.members[index].read_block(offset, buf)
+    }
+
+    fn write_block(&mut self, block: u64, buf: &dyn AsRef<[u8]>) -> Result<usize> {
+        let (index, offset, _) = self.locate(block)?;
+        self.members[index].write_block(offset, buf)
+    }
+
+    fn read_blocks(&self, start: u64, count: usize, buf: &mut dyn AsMut<[u8]>) -> Result<usize> {
+        let buffer = buf.as_mut();
+        self.check_range(start, count, buffer.len())?;
+        let mut done = 0;
+        while done < count {
+            let (index, offset, remaining) = self.locate(start + done as u64)?;
+            let n = remaining.min(count - done);
+            let range = done * self.block_size..(done + n) * self.block_size;
+            let _ = self.members[index].read_blocks(offset, n, &mut &mut buffer[range])?;
+            done += n;
+        }
+        Ok(count * self.block_size)
+    }
+
+    fn write_blocks(&mut self, start: u64, count: usize, buf: &dyn AsRef<[u8]>) -> Result<usize> {
+        let buffer = buf.as_ref();
+        self.check_range(start, count, buffer.len())?;
+        let mut done = 0;
+        while done < count {
+            let (index, offset, remaining) = self.locate(start + done as u64)?;
+            let n = remaining.min(count - done);
+            let range = done * self.block_size..(done + n) * self.block_size;
+            let _ = self.members[index].write_blocks(offset, n, &&buffer[range])?;
+            done += n;
+        }
+        Ok(count * self.block_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use crate::io::block::linear::LinearDevice;
+    use crate::io::block::memory::MemoryBlockDevice;
+    use crate::io::block::one::OneDevice;
+    use crate::io::block::trace::{Call, Capture, TracingDevice};
+    use crate::io::block::BlockDevice;
+    use crate::io::{Error, ReadAt};
+
+    #[test]
+    fn test_linear_mapping() {
+        let mut device = LinearDevice::new(vec![
+            MemoryBlockDevice::new(2, 3),
+            MemoryBlockDevice::new(2, 0),
+            MemoryBlockDevice::new(2, 2),
+        ])
+        .unwrap();
+        assert_eq!(5, device.block_count());
+        assert_eq!(3, device.start(2));
+        let data: Vec<u8> = (0..10).collect();
+        assert_eq!(Ok(10), device.write_blocks(0, 5, &data));
+        assert_eq!(Err(Error::NoSuchBlock), device.write_block(5, &[0_u8; 2]));
+
+        let mut block = [0_u8; 2];
+        assert_eq!(Ok(2), device.read_block(3, &mut block));
+        assert_eq!([6, 7], block);
+        let members = device.into_inner();
+        assert_eq!([0, 1, 2, 3, 4, 5], members[0].get_ref()[..]);
+        assert_eq!([6, 7, 8, 9], members[2].get_ref()[..]);
+    }
+
+    #[test]
+    fn test_linear_spanning_read() {
+        let device = LinearDevice::new(vec![
+            TracingDevice::new(OneDevice::new(4, 3), Capture::Calls),
+            TracingDevice::new(OneDevice::new(4, 3), Capture::Calls),
+        ])
+        .unwrap();
+        let mut data = [0_u8; 16];
+        assert_eq!(Ok(16), device.read_at(4, &mut data));
+        assert_eq!([1_u8; 16], data);
+        for member in device.members() {
+            assert_eq!(
+                1,
+                member.count(|call| matches!(call, Call::ReadBlocks { .. }))
+            );
+        }
+        assert_eq!(
+            Err(Error::NoSuchBlock),
+            device.read_blocks(4, 3, &mut [0_u8; 12])
+        );
+    }
+
+    #[test]
+    fn test_linear_invalid() {
+        assert!(LinearDevice::<MemoryBlockDevice>::new(vec![]).is_err());
+        assert!(LinearDevice::new(vec![
+            MemoryBlockDevice::new(4, 4),
+            MemoryBlockDevice::new(2, 8)
+        ])
+        .is_err());
+        let mut device = LinearDevice::new(vec![MemoryBlockDevice::new(2, 2)]).unwrap();
+        assert_eq!(
+            Err(Error::BufferTooSmall),
+            device.write_blocks(0, 2, &[0_u8; 3])
+        );
+    }
+}
@@ -0,0 +1,199 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::io::block::BlockDevice;
+use crate::io::{Error, Result};
+
+/// A [`BlockDevice`] that keeps all of its blocks in memory.
+///
+/// The device is backed by anything that can be viewed as a byte slice,
+/// usually a [`Vec<u8>`] or a borrowed `&mut [u8]`. Block `n` is stored
+/// at bytes `n * block_size..(n + 1) * block_size` of the backing memory.
+pub struct MemoryBlockDevice<T = Vec<u8>> {
+    inner: T,
+    block_size: usize,
+    block_count: usize,
+}
+
+impl MemoryBlockDevice<Vec<u8>> {
+    /// Creates a new device with the given geometry, where every byte is zero.
+    pub fn new(block_size: usize, block_count: usize) -> Self {
+        Self::from_image(vec![0_u8; block_size * block_count], block_size)
+    }
+}
+
+impl<T> MemoryBlockDevice<T>
+where
+    T: AsRef<[u8]>,
+{
+    /// Creates a new device from an existing image. The number of blocks is
+    /// the length of the image divided by the block size. If the length is not
+    /// a multiple of the block size, the trailing bytes are not accessible
+    /// through the device.
+    ///
+    /// # Panics
+    /// Panics if the block size is zero.
+    pub fn from_image(image: T, block_size: usize) -> Self {
+        assert_ne!(0, block_size, "block size must not be zero");
+        Self {
+            block_count: image.as_ref().len() / block_size,
+            inner: image,
+            block_size,
+        }
+    }
+}
+
+impl<T> MemoryBlockDevice<T> {
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    pub const fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Returns the range of bytes in the backing memory that
+    /// holds the given number of blocks, starting at the given block.
+    fn range(&self, block: u64, count: usize) -> Result<Range<usize>> {
+        match block.checked_add(count as u64) {
+            Some(end) if block < self.block_count as u64 && end <= self.block_count as u64 => {
+                let start = block as usize * self.block_size;
+                Ok(start..start + count * self.block_size)
+            }
+            _ => Err(Error::NoSuchBlock),
+        }
+    }
+}
+
+impl<T> BlockDevice for MemoryBlockDevice<T>
+where
+    T: AsRef<[u8]> + AsMut<[u8]>,
+{
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn block_count(&self) -> usize {
+        self.block_count
+    }
+
+    fn read_block(&self, block: u64, buf: &mut dyn AsMut<[u8]>) -> Result<usize> {
+        let buffer = buf.as_mut();
+        if buffer.len() < self.block_size {
+            return Err(Error::BufferTooSmall);
+        }
+        let range = self.range(block, 1)?;
+
+        buffer[0..self.block_size].copy_from_slice(&self.inner.as_ref()[range]);
+        Ok(self.block_size)
+    }
+
+    fn write_block(&mut self, block: u64, buf: &dyn AsRef<[u8]>) -> Result<usize> {
+        let buffer = buf.as_ref();
+        if buffer.len() < self.block_size {
+            return Err(Error::BufferTooSmall);
+        }
+        let range = self.range(block, 1)?;
+
+        self.inner.as_mut()[range].copy_from_slice(&buffer[0..self.block_size]);
+        Ok(self.block_size)
+    }
+
+    fn read_blocks(&self, start: u64, count: usize, buf: &mut dyn AsMut<[u8]>) -> Result<usize> {
+        let buffer = buf.as_mut();
+        let len = count * self.block_size;
+        if buffer.len() < len {
+            return Err(Error::BufferTooSmall);
+        }
+        let range = self.range(start, count)?;
+
+        buffer[0..len].copy_from_slice(&self.inner.as_ref()[range]);
+        Ok(len)
+    }
+
+    fn write_blocks(&mut self, start: u64, count: usize, buf: &dyn AsRef<[u8]>) -> Result<usize> {
+        let buffer = buf.as_ref();
+        let len = count * self.block_size;
+        if buffer.len() < len {
+            return Err(Error::BufferTooSmall);
+        }
+        let range = self.range(start, count)?;
+
+        self.inner.as_mut()[range].copy_from_slice(&buffer[0..len]);
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use crate::io::block::memory::MemoryBlockDevice;
+    use crate::io::block::BlockDevice;
+    use crate::io::Error;
+
+    #[test]
+    fn test_memory_read_write() {
+        let mut device = MemoryBlockDevice::new(4, 3);
+        assert_eq!(3, device.block_count());
+        device.write_block(1, &[1_u8, 2, 3, 4]).unwrap();
+        let mut data = [0_u8; 4];
+        device.read_block(1, &mut data).unwrap();
+        assert_eq!([1, 2, 3, 4], data);
+        device.read_block(2, &mut data).unwrap();
+        assert_eq!([0, 0, 0, 0], data);
+        assert_eq!(
+            vec![0, 0, 0, 0, 1, 2, 3, 4, 0, 0, 0, 0],
+            device.into_inner()
+        );
+    }
+
+    #[test]
+    fn test_memory_bounds() {
+        let mut device = MemoryBlockDevice::new(4, 3);
+        let mut data = [0_u8; 4];
+        assert_eq!(Err(Error::NoSuchBlock), device.read_block(3, &mut data));
+        assert_eq!(Err(Error::NoSuchBlock), device.write_block(3, &data));
+        assert_eq!(
+            Err(Error::BufferTooSmall),
+            device.read_block(0, &mut [0_u8; 3])
+        );
+        assert_eq!(
+            Err(Error::BufferTooSmall),
+            device.write_block(0, &[0_u8; 3])
+        );
+    }
+
+    #[test]
+    fn test_memory_multiple_blocks() {
+        let mut device = MemoryBlockDevice::new(2, 4);
+        device.write_blocks(1, 2, &[1_u8, 2, 3, 4, 5]).unwrap();
+        let mut data = [0_u8; 6];
+        device.read_blocks(0, 3, &mut data).unwrap();
+        assert_eq!([0, 0, 1, 2, 3, 4], data);
+        assert_eq!(Err(Error::NoSuchBlock), device.read_blocks(2, 3, &mut data));
+        assert_eq!(
+            Err(Error::BufferTooSmall),
+            device.write_blocks(0, 4, &[0_u8; 7])
+        );
+        assert_eq!(vec![0, 0, 1, 2, 3, 4, 0, 0], device.into_inner());
+    }
+
+    #[test]
+    fn test_memory_borrowed_image() {
+        let mut image = [0_u8, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+        let mut device = MemoryBlockDevice::from_image(&mut image[..], 3);
+        // the trailing byte doesn't form a full block
+        assert_eq!(3, device.block_count());
+        let mut data = [0_u8; 3];
+        device.read_block(2, &mut data).unwrap();
+        assert_eq!([6, 7, 8], data);
+        device.write_block(0, &[9_u8, 9, 9]).unwrap();
+        assert_eq!([9, 9, 9, 3, 4, 5, 6, 7, 8, 9], image);
+    }
+}
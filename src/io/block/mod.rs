@@ -1,12 +1,26 @@
-use alloc::vec;
 use alloc::vec::Vec;
 
-use crate::io::ReadAt;
-use crate::io::Result;
+use crate::io::{Error, Result};
+use crate::io::{ReadAt, WriteAt};
 
+pub mod adapter;
 pub mod cache;
+pub mod compressed;
 pub mod cow;
+pub mod encrypted;
+pub mod faulty;
+pub mod integrity;
+pub mod journal;
+pub mod linear;
+pub mod memory;
 pub mod one;
+pub mod partition;
+pub mod raid;
+pub mod snapshot;
+pub mod sparse;
+pub mod stream;
+pub mod sub;
+pub mod trace;
 
 /// Describes a device that stores data in blocks of a fixed size.
 pub trait BlockDevice {
@@ -23,39 +37,121 @@ pub trait BlockDevice {
     /// The buffer must be at least as large as the [`BlockDevice::block_size`].
     /// Failing to provide such a buffer will result in an [`Error::BufferTooSmall`].
     fn write_block(&mut self, block: u64, buf: &dyn AsRef<[u8]>) -> Result<usize>;
+    /// Read `count` consecutive blocks, starting with the given block number, from this
+    /// device into the given buffer. The buffer must be at least `count` times as large
+    /// as the [`BlockDevice::block_size`], otherwise this fails with [`Error::BufferTooSmall`].
+    ///
+    /// The default implementation calls [`BlockDevice::read_block`] for every block.
+    /// Devices that can transfer multiple blocks at once should override this.
+    fn read_blocks(&self, start: u64, count: usize, buf: &mut dyn AsMut<[u8]>) -> Result<usize> {
+        let buffer = buf.as_mut();
+        let block_size = self.block_size();
+        let len = count * block_size;
+        if buffer.len() < len {
+            return Err(Error::BufferTooSmall);
+        }
+
+        for (i, chunk) in buffer[..len].chunks_exact_mut(block_size).enumerate() {
+            self.read_block(start + i as u64, &mut &mut *chunk)?;
+        }
+        Ok(len)
+    }
+    /// Write `count` consecutive blocks, starting with the given block number, from the
+    /// given buffer to this device. The buffer must be at least `count` times as large
+    /// as the [`BlockDevice::block_size`], otherwise this fails with [`Error::BufferTooSmall`].
+    ///
+    /// The default implementation calls [`BlockDevice::write_block`] for every block.
+    /// Devices that can transfer multiple blocks at once should override this.
+    fn write_blocks(&mut self, start: u64, count: usize, buf: &dyn AsRef<[u8]>) -> Result<usize> {
+        let buffer = buf.as_ref();
+        let block_size = self.block_size();
+        let len = count * block_size;
+        if buffer.len() < len {
+            return Err(Error::BufferTooSmall);
+        }
+
+        for (i, chunk) in buffer[..len].chunks_exact(block_size).enumerate() {
+            self.write_block(start + i as u64, &chunk)?;
+        }
+        Ok(len)
+    }
 }
 
 impl<T> ReadAt<u8> for T
 where
     T: BlockDevice,
 {
+    /// Reads from this device at the given offset into the given buffer. All blocks that
+    /// are fully covered by the buffer are read directly into it with a single call to
+    /// [`BlockDevice::read_blocks`].
     fn read_at(&self, offset: u64, buf: &mut dyn AsMut<[u8]>) -> Result<usize> {
         let buffer = buf.as_mut();
         let block_size = self.block_size();
-        if offset % buffer.len() as u64 == 0 && buffer.len() == block_size {
-            // if we read exactly one block, and that read is aligned, delegate to the device impl
-            return self.read_block(offset / block_size as u64, buf);
+
+        // only needed for the partially covered blocks at the start and the end
+        let mut block_data: Vec<u8> = Vec::new();
+        let mut done = 0;
+        while done < buffer.len() {
+            let position = offset + done as u64;
+            let block = position / block_size as u64;
+            let relative_offset = position as usize % block_size;
+            let remaining = buffer.len() - done;
+
+            if relative_offset == 0 && remaining >= block_size {
+                let count = remaining / block_size;
+                let len = count * block_size;
+                self.read_blocks(block, count, &mut &mut buffer[done..done + len])?;
+                done += len;
+            } else {
+                let len = (block_size - relative_offset).min(remaining);
+                block_data.resize(block_size, 0);
+                self.read_block(block, &mut block_data)?;
+                buffer[done..done + len]
+                    .copy_from_slice(&block_data[relative_offset..relative_offset + len]);
+                done += len;
+            }
         }
 
-        let start_block = offset / block_size as u64;
-        let end_block = (offset + buffer.len() as u64) / block_size as u64;
-        let relative_offset = offset as usize % block_size;
-        let block_count = if relative_offset == 0 && start_block != end_block {
-            end_block - start_block
-        } else {
-            end_block - start_block + 1
-        } as usize;
-
-        // read blocks
-        let mut data: Vec<u8> = vec![0_u8; block_count * block_size];
-        for i in 0..block_count {
-            let start_index = i * block_size;
-            let end_index = start_index + block_size;
-            let read_block_index = start_block + i as u64;
-
-            self.read_block(read_block_index, &mut &mut data[start_index..end_index])?;
+        Ok(buffer.len())
+    }
+}
+
+impl<T> WriteAt<u8> for T
+where
+    T: BlockDevice,
+{
+    /// Writes the given buffer to this device at the given offset. All blocks that are
+    /// fully covered by the buffer are written with a single call to [`BlockDevice::write_blocks`].
+    /// Blocks that are only partially covered are read from the device first, and
+    /// written back with the modified range (read-modify-write).
+    fn write_at(&mut self, offset: u64, buf: &dyn AsRef<[u8]>) -> Result<usize> {
+        let buffer = buf.as_ref();
+        let block_size = self.block_size();
+
+        // only needed for the partially covered blocks at the start and the end
+        let mut block_data: Vec<u8> = Vec::new();
+        let mut done = 0;
+        while done < buffer.len() {
+            let position = offset + done as u64;
+            let block = position / block_size as u64;
+            let relative_offset = position as usize % block_size;
+            let remaining = buffer.len() - done;
+
+            if relative_offset == 0 && remaining >= block_size {
+                let count = remaining / block_size;
+                let len = count * block_size;
+                self.write_blocks(block, count, &&buffer[done..done + len])?;
+                done += len;
+            } else {
+                let len = (block_size - relative_offset).min(remaining);
+                block_data.resize(block_size, 0);
+                self.read_block(block, &mut block_data)?;
+                block_data[relative_offset..relative_offset + len]
+                    .copy_from_slice(&buffer[done..done + len]);
+                self.write_block(block, &block_data)?;
+                done += len;
+            }
         }
-        buffer.copy_from_slice(&data[relative_offset..relative_offset + buffer.len()]);
 
         Ok(buffer.len())
     }
@@ -64,10 +160,12 @@ where
 #[cfg(test)]
 mod tests {
     use alloc::vec;
+    use alloc::vec::Vec;
 
+    use crate::io::block::memory::MemoryBlockDevice;
     use crate::io::block::BlockDevice;
-    use crate::io::ReadAt;
-    use crate::io::Result;
+    use crate::io::{Error, Result};
+    use crate::io::{ReadAt, WriteAt};
 
     struct TestBlockDevice {
         block_size: usize,
@@ -147,4 +245,98 @@ mod tests {
             data
         );
     }
+
+    #[test]
+    fn test_read_blocks_default() {
+        let dev = TestBlockDevice {
+            block_size: 3,
+            block_count: 5,
+        };
+        let mut data = vec![0_u8; 10];
+
+        assert_eq!(Ok(9), dev.read_blocks(2, 3, &mut data));
+        assert_eq!(vec![3, 3, 3, 4, 4, 4, 5, 5, 5, 0], data);
+        assert_eq!(Err(Error::BufferTooSmall), dev.read_blocks(0, 4, &mut data));
+    }
+
+    #[test]
+    fn test_read_at_empty() {
+        let dev = TestBlockDevice {
+            block_size: 512,
+            block_count: 1,
+        };
+
+        assert_eq!(Ok(0), dev.read_at(3, &mut [0_u8; 0]));
+    }
+
+    #[test]
+    fn test_read_at_memory_7() {
+        let dev = memory_device(7, 40);
+        let mut data = vec![0_u8; 50];
+
+        dev.read_at(19, &mut &mut data[5..37]).unwrap();
+        assert_eq!(
+            vec![
+                0, 0, 0, 0, 0, 3, 3, 4, 4, 4, 4, 4, 4, 4, 5, 5, 5, 5, 5, 5, 5, 6, 6, 6, 6, 6, 6, 6,
+                7, 7, 7, 7, 7, 7, 7, 8, 8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            ],
+            data
+        );
+    }
+
+    /// Creates a device where every byte of a block is the block number plus one,
+    /// just like the reads of [`TestBlockDevice`].
+    fn memory_device(block_size: usize, block_count: usize) -> MemoryBlockDevice {
+        let image: Vec<u8> = (0..block_count)
+            .flat_map(|block| vec![block as u8 + 1; block_size])
+            .collect();
+        MemoryBlockDevice::from_image(image, block_size)
+    }
+
+    #[test]
+    fn test_write_at_0() {
+        let mut dev = memory_device(512, 2);
+
+        dev.write_at(0, &[9_u8; 512]).unwrap();
+        let image = dev.into_inner();
+        assert_eq!(vec![9_u8; 512], image[..512]);
+        assert_eq!(vec![2_u8; 512], image[512..]);
+    }
+
+    #[test]
+    fn test_write_at_512() {
+        let mut dev = memory_device(512, 3);
+
+        dev.write_at(512, &[9_u8; 1024]).unwrap();
+        let image = dev.into_inner();
+        assert_eq!(vec![1_u8; 512], image[..512]);
+        assert_eq!(vec![9_u8; 1024], image[512..]);
+    }
+
+    #[test]
+    fn test_write_at_7() {
+        let mut dev = memory_device(7, 8);
+        let data: Vec<u8> = (100..132).collect();
+
+        dev.write_at(19, &&data[5..30]).unwrap();
+        assert_eq!(
+            vec![
+                1, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 3, 3, 3, 3, 3, 105, 106, 107, 108, 109,
+                110, 111, 112, 113, 114, 115, 116, 117, 118, 119, 120, 121, 122, 123, 124, 125,
+                126, 127, 128, 129, 7, 7, 7, 7, 7, 8, 8, 8, 8, 8, 8, 8,
+            ],
+            dev.into_inner()
+        );
+    }
+
+    #[test]
+    fn test_write_at_within_block() {
+        let mut dev = memory_device(7, 2);
+
+        dev.write_at(9, &[9_u8, 9]).unwrap();
+        assert_eq!(
+            vec![1, 1, 1, 1, 1, 1, 1, 2, 2, 9, 9, 2, 2, 2],
+            dev.into_inner()
+        );
+    }
 }
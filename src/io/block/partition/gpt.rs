@@ -0,0 +1,327 @@
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::{Display, Formatter};
+
+use crate::checksum::crc32;
+use crate::io::block::BlockDevice;
+use crate::io::cursor::Cursor;
+use crate::io::{Error, Read, ReadAt, Result};
+use crate::{read_bytes, read_le_u16, read_le_u32, read_le_u64};
+
+const SIGNATURE: &[u8; 8] = b"EFI PART";
+/// The size of the header fields defined by the UEFI specification.
+const MIN_HEADER_SIZE: u32 = 92;
+const MIN_ENTRY_SIZE: u32 = 128;
+const NAME_LENGTH: usize = 36;
+
+/// A globally unique identifier, stored in the mixed-endian
+/// layout that GPT uses.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Guid(pub [u8; 16]);
+
+impl Guid {
+    pub const NIL: Guid = Guid([0; 16]);
+
+    pub fn is_nil(&self) -> bool {
+        *self == Self::NIL
+    }
+}
+
+impl Display for Guid {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let b = &self.0;
+        write!(
+            f,
+            "{:08X}-{:04X}-{:04X}-",
+            u32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+            u16::from_le_bytes([b[4], b[5]]),
+            u16::from_le_bytes([b[6], b[7]]),
+        )?;
+        for (i, byte) in b[8..].iter().enumerate() {
+            if i == 2 {
+                write!(f, "-")?;
+            }
+            write!(f, "{:02X}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// A GUID partition table, consisting of the primary header and its partition entries.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Gpt {
+    pub header: GptHeader,
+    /// The used partition entries, in the order they appear in the table.
+    pub partitions: Vec<GptPartition>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct GptHeader {
+    pub revision: u32,
+    pub header_size: u32,
+    pub current_lba: u64,
+    pub backup_lba: u64,
+    pub first_usable_lba: u64,
+    pub last_usable_lba: u64,
+    pub disk_guid: Guid,
+    pub partition_entry_lba: u64,
+    pub partition_entry_count: u32,
+    pub partition_entry_size: u32,
+    pub partition_entries_crc32: u32,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct GptPartition {
+    pub type_guid: Guid,
+    pub unique_guid: Guid,
+    pub first_lba: u64,
+    /// The last block of the partition, inclusive.
+    pub last_lba: u64,
+    pub attributes: u64,
+    pub name: String,
+}
+
+impl Gpt {
+    /// Reads the primary GPT header from the second block of the given device,
+    /// and the partition entries it points to.
+    ///
+    /// Returns [`Error::InvalidMagicNumber`] if the header signature is missing
+    /// or the blocks of the device are too small to hold a header, and
+    /// [`Error::IncoherentData`] if the checksum of the header or of the
+    /// partition entries doesn't match, or if the header is otherwise malformed.
+    pub fn read<D>(device: &D) -> Result<Self>
+    where
+        D: BlockDevice,
+    {
+        let block_size = device.block_size();
+        if block_size < MIN_HEADER_SIZE as usize {
+            return Err(Error::InvalidMagicNumber);
+        }
+        let mut data = vec![0_u8; block_size];
+        device.read_block(1, &mut data)?;
+        if &data[0..8] != SIGNATURE {
+            return Err(Error::InvalidMagicNumber);
+        }
+
+        let mut c = Cursor::new(&data[8..]);
+        let revision = read_le_u32!(c);
+        let header_size = read_le_u32!(c);
+        let header_crc32 = read_le_u32!(c);
+        let _reserved = read_le_u32!(c);
+        let header = GptHeader {
+            revision,
+            header_size,
+            current_lba: read_le_u64!(c),
+            backup_lba: read_le_u64!(c),
+            first_usable_lba: read_le_u64!(c),
+            last_usable_lba: read_le_u64!(c),
+            disk_guid: Guid(read_bytes!(c, 16)),
+            partition_entry_lba: read_le_u64!(c),
+            partition_entry_count: read_le_u32!(c),
+            partition_entry_size: read_le_u32!(c),
+            partition_entries_crc32: read_le_u32!(c),
+        };
+
+        if header_size < MIN_HEADER_SIZE || header_size as usize > block_size {
+            return Err(Error::IncoherentData);
+        }
+        // the checksum is calculated with the checksum field set to zero
+        let header_data = &mut data[..header_size as usize];
+        header_data[16..20].fill(0);
+        if crc32(header_data) != header_crc32 {
+            return Err(Error::IncoherentData);
+        }
+
+        let entries = Self::read_entries(device, &header)?;
+        Ok(Self {
+            header,
+            partitions: entries,
+        })
+    }
+
+    fn read_entries<D>(device: &D, header: &GptHeader) -> Result<Vec<GptPartition>>
+    where
+        D: BlockDevice,
+    {
+        let entry_size = header.partition_entry_size as u64;
+        if entry_size < MIN_ENTRY_SIZE as u64 || !entry_size.is_multiple_of(8) {
+            return Err(Error::IncoherentData);
+        }
+        let device_size = device.block_count() as u64 * device.block_size() as u64;
+        let len = header.partition_entry_count as u64 * entry_size;
+        let offset = header
+            .partition_entry_lba
+            .checked_mul(device.block_size() as u64)
+            .filter(|offset| {
+                offset
+                    .checked_add(len)
+                    .is_some_and(|end| end <= device_size)
+            })
+            .ok_or(Error::IncoherentData)?;
+
+        let mut data = vec![0_u8; len as usize];
+        device.read_at(offset, &mut data)?;
+        if crc32(&data) != header.partition_entries_crc32 {
+            return Err(Error::IncoherentData);
+        }
+
+        let mut partitions = Vec::new();
+        for entry in data.chunks_exact(entry_size as usize) {
+            let mut c = Cursor::new(entry);
+            let type_guid = Guid(read_bytes!(c, 16));
+            if type_guid.is_nil() {
+                // unused entry
+                continue;
+            }
+            let unique_guid = Guid(read_bytes!(c, 16));
+            let first_lba = read_le_u64!(c);
+            let last_lba = read_le_u64!(c);
+            let attributes = read_le_u64!(c);
+            let mut name = [0_u16; NAME_LENGTH];
+            for unit in name.iter_mut() {
+                *unit = read_le_u16!(c);
+            }
+
+            if last_lba < first_lba {
+                return Err(Error::IncoherentData);
+            }
+            let len = name.iter().position(|&u| u == 0).unwrap_or(NAME_LENGTH);
+            partitions.push(GptPartition {
+                type_guid,
+                unique_guid,
+                first_lba,
+                last_lba,
+                attributes,
+                name: char::decode_utf16(name[..len].iter().copied())
+                    .map(|r| r.unwrap_or(char::REPLACEMENT_CHARACTER))
+                    .collect(),
+            });
+        }
+        Ok(partitions)
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use alloc::string::ToString;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use crate::checksum::crc32;
+    use crate::io::block::memory::MemoryBlockDevice;
+    use crate::io::block::partition::gpt::{Gpt, Guid};
+    use crate::io::Error;
+
+    pub const EFI_SYSTEM_PARTITION: Guid = Guid([
+        0x28, 0x73, 0x2A, 0xC1, 0x1F, 0xF8, 0xD2, 0x11, 0xBA, 0x4B, 0x00, 0xA0, 0xC9, 0x3E, 0xC9,
+        0x3B,
+    ]);
+
+    /// Creates an image with a protective MBR and a GPT with the given partitions,
+    /// given as first and last block.
+    pub fn image(block_size: usize, block_count: usize, partitions: &[(u64, u64)]) -> Vec<u8> {
+        let mut image = vec![0_u8; block_size * block_count];
+        // protective MBR
+        image[450] = 0xEE;
+        image[454..458].copy_from_slice(&1_u32.to_le_bytes());
+        image[458..462].copy_from_slice(&(block_count as u32 - 1).to_le_bytes());
+        image[510] = 0x55;
+        image[511] = 0xAA;
+
+        // 4 entries at block 2
+        let mut entries = vec![0_u8; 4 * 128];
+        for (i, &(first, last)) in partitions.iter().enumerate() {
+            let entry = &mut entries[i * 128..(i + 1) * 128];
+            entry[0..16].copy_from_slice(&EFI_SYSTEM_PARTITION.0);
+            entry[16] = i as u8 + 1;
+            entry[32..40].copy_from_slice(&first.to_le_bytes());
+            entry[40..48].copy_from_slice(&last.to_le_bytes());
+            for (j, c) in "part".encode_utf16().enumerate() {
+                entry[56 + j * 2..58 + j * 2].copy_from_slice(&c.to_le_bytes());
+            }
+            entry[64] = b'0' + i as u8;
+        }
+        image[2 * block_size..2 * block_size + entries.len()].copy_from_slice(&entries);
+
+        let header = &mut image[block_size..block_size + 92];
+        header[0..8].copy_from_slice(b"EFI PART");
+        header[8..12].copy_from_slice(&0x0001_0000_u32.to_le_bytes());
+        header[12..16].copy_from_slice(&92_u32.to_le_bytes());
+        header[24..32].copy_from_slice(&1_u64.to_le_bytes());
+        header[32..40].copy_from_slice(&(block_count as u64 - 1).to_le_bytes());
+        header[40..48].copy_from_slice(&3_u64.to_le_bytes());
+        header[48..56].copy_from_slice(&(block_count as u64 - 2).to_le_bytes());
+        header[56..72].fill(0xAB);
+        header[72..80].copy_from_slice(&2_u64.to_le_bytes());
+        header[80..84].copy_from_slice(&4_u32.to_le_bytes());
+        header[84..88].copy_from_slice(&128_u32.to_le_bytes());
+        header[88..92].copy_from_slice(&crc32(&entries).to_le_bytes());
+        let crc = crc32(header);
+        header[16..20].copy_from_slice(&crc.to_le_bytes());
+        image
+    }
+
+    #[test]
+    fn test_guid_display() {
+        assert_eq!(
+            "C12A7328-F81F-11D2-BA4B-00A0C93EC93B",
+            EFI_SYSTEM_PARTITION.to_string()
+        );
+    }
+
+    #[test]
+    fn test_gpt_read() {
+        let device = MemoryBlockDevice::from_image(image(512, 16, &[(3, 5), (6, 13)]), 512);
+        let gpt = Gpt::read(&device).unwrap();
+        assert_eq!(0x0001_0000, gpt.header.revision);
+        assert_eq!(Guid([0xAB; 16]), gpt.header.disk_guid);
+        assert_eq!(2, gpt.partitions.len());
+        let p = &gpt.partitions[1];
+        assert_eq!(EFI_SYSTEM_PARTITION, p.type_guid);
+        assert_eq!(2, p.unique_guid.0[0]);
+        assert_eq!((6, 13), (p.first_lba, p.last_lba));
+        assert_eq!("part1", p.name);
+    }
+
+    #[test]
+    fn test_gpt_invalid_signature() {
+        let mut image = image(512, 16, &[(3, 5)]);
+        image[512] = b'X';
+        let device = MemoryBlockDevice::from_image(image, 512);
+        assert_eq!(Err(Error::InvalidMagicNumber), Gpt::read(&device));
+    }
+
+    #[test]
+    fn test_gpt_small_blocks() {
+        let device = MemoryBlockDevice::new(4, 16);
+        assert_eq!(Err(Error::InvalidMagicNumber), Gpt::read(&device));
+
+        let mut image = vec![0_u8; 64 * 4];
+        image[64..72].copy_from_slice(b"EFI PART");
+        let device = MemoryBlockDevice::from_image(image, 64);
+        assert_eq!(Err(Error::InvalidMagicNumber), Gpt::read(&device));
+    }
+
+    #[test]
+    fn test_gpt_reversed_partition() {
+        let device = MemoryBlockDevice::from_image(image(512, 16, &[(5, 3)]), 512);
+        assert_eq!(Err(Error::IncoherentData), Gpt::read(&device));
+    }
+
+    #[test]
+    fn test_gpt_header_crc_mismatch() {
+        let mut image = image(512, 16, &[(3, 5)]);
+        image[512 + 40] ^= 1;
+        let device = MemoryBlockDevice::from_image(image, 512);
+        assert_eq!(Err(Error::IncoherentData), Gpt::read(&device));
+    }
+
+    #[test]
+    fn test_gpt_entries_crc_mismatch() {
+        let mut image = image(512, 16, &[(3, 5)]);
+        image[1024 + 32] ^= 1;
+        let device = MemoryBlockDevice::from_image(image, 512);
+        assert_eq!(Err(Error::IncoherentData), Gpt::read(&device));
+    }
+}
@@ -0,0 +1,161 @@
+use alloc::vec::Vec;
+
+use crate::io::block::BlockDevice;
+use crate::io::cursor::Cursor;
+use crate::io::{Error, Read, ReadAt, Result};
+use crate::{read_bytes, read_le_u32, read_u8};
+
+/// The partition type that a protective MBR uses to indicate
+/// that the disk is partitioned with a GPT.
+pub const PROTECTIVE_PARTITION_TYPE: u8 = 0xEE;
+
+const BOOT_SIGNATURE: [u8; 2] = [0x55, 0xAA];
+
+/// A master boot record, the legacy PC partition table in the first 512 bytes of a disk.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Mbr {
+    pub disk_signature: u32,
+    /// The used partition entries, in the order they appear in the table.
+    pub partitions: Vec<MbrPartition>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct MbrPartition {
+    pub bootable: bool,
+    pub partition_type: u8,
+    /// The first block of the partition. The MBR counts in logical blocks
+    /// of the disk, which are the blocks of the [`BlockDevice`].
+    pub start_lba: u32,
+    pub sector_count: u32,
+}
+
+impl Mbr {
+    /// Reads the MBR from the first 512 bytes of the given device.
+    ///
+    /// Returns [`Error::InvalidMagicNumber`] if the boot signature is missing,
+    /// and [`Error::IncoherentData`] if an entry has an invalid status byte,
+    /// which is the case for boot sectors that are not an MBR.
+    pub fn read<D>(device: &D) -> Result<Self>
+    where
+        D: BlockDevice,
+    {
+        let mut data = [0_u8; 512];
+        device.read_at(0, &mut data)?;
+        if data[510..512] != BOOT_SIGNATURE {
+            return Err(Error::InvalidMagicNumber);
+        }
+
+        let disk_signature = u32::from_le_bytes(data[440..444].try_into().unwrap());
+        let mut c = Cursor::new(&data[446..510]);
+        let mut partitions = Vec::with_capacity(4);
+        for _ in 0..4 {
+            let status = read_u8!(c);
+            let _chs_first = read_bytes!(c, 3);
+            let partition_type = read_u8!(c);
+            let _chs_last = read_bytes!(c, 3);
+            let start_lba = read_le_u32!(c);
+            let sector_count = read_le_u32!(c);
+
+            if status != 0x00 && status != 0x80 {
+                return Err(Error::IncoherentData);
+            }
+            if partition_type == 0 {
+                // unused entry
+                continue;
+            }
+            partitions.push(MbrPartition {
+                bootable: status == 0x80,
+                partition_type,
+                start_lba,
+                sector_count,
+            });
+        }
+
+        Ok(Self {
+            disk_signature,
+            partitions,
+        })
+    }
+
+    /// Whether this is a protective MBR, meaning that
+    /// the actual partition table is a GPT.
+    pub fn is_protective(&self) -> bool {
+        self.partitions
+            .iter()
+            .any(|p| p.partition_type == PROTECTIVE_PARTITION_TYPE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use crate::io::block::memory::MemoryBlockDevice;
+    use crate::io::block::partition::mbr::{Mbr, MbrPartition};
+    use crate::io::Error;
+
+    fn image() -> MemoryBlockDevice {
+        let mut image = vec![0_u8; 512 * 4];
+        image[440..444].copy_from_slice(&0xDEAD_BEEF_u32.to_le_bytes());
+        image[446] = 0x80;
+        image[450] = 0x83;
+        image[454..458].copy_from_slice(&1_u32.to_le_bytes());
+        image[458..462].copy_from_slice(&2_u32.to_le_bytes());
+        // the second entry is unused
+        image[478] = 0x00;
+        image[482] = 0x0C;
+        image[486..490].copy_from_slice(&3_u32.to_le_bytes());
+        image[490..494].copy_from_slice(&1_u32.to_le_bytes());
+        image[510] = 0x55;
+        image[511] = 0xAA;
+        MemoryBlockDevice::from_image(image, 512)
+    }
+
+    #[test]
+    fn test_mbr_read() {
+        let mbr = Mbr::read(&image()).unwrap();
+        assert_eq!(
+            Mbr {
+                disk_signature: 0xDEAD_BEEF,
+                partitions: vec![
+                    MbrPartition {
+                        bootable: true,
+                        partition_type: 0x83,
+                        start_lba: 1,
+                        sector_count: 2,
+                    },
+                    MbrPartition {
+                        bootable: false,
+                        partition_type: 0x0C,
+                        start_lba: 3,
+                        sector_count: 1,
+                    },
+                ],
+            },
+            mbr
+        );
+        assert!(!mbr.is_protective());
+    }
+
+    #[test]
+    fn test_mbr_small_blocks() {
+        let device = MemoryBlockDevice::from_image(image().into_inner(), 128);
+        assert_eq!(2, Mbr::read(&device).unwrap().partitions.len());
+    }
+
+    #[test]
+    fn test_mbr_invalid_signature() {
+        let mut image = image().into_inner();
+        image[511] = 0;
+        let device = MemoryBlockDevice::from_image(image, 512);
+        assert_eq!(Err(Error::InvalidMagicNumber), Mbr::read(&device));
+    }
+
+    #[test]
+    fn test_mbr_invalid_status() {
+        let mut image = image().into_inner();
+        image[446] = 0x12;
+        let device = MemoryBlockDevice::from_image(image, 512);
+        assert_eq!(Err(Error::IncoherentData), Mbr::read(&device));
+    }
+}
@@ -0,0 +1,142 @@
+pub use gpt::{Gpt, GptHeader, GptPartition, Guid};
+pub use mbr::{Mbr, MbrPartition};
+
+use crate::io::block::sub::SubDevice;
+use crate::io::block::BlockDevice;
+use crate::io::{Error, Result};
+
+pub mod gpt;
+pub mod mbr;
+
+/// The partition table of a disk, which is either a legacy MBR or a GPT.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum PartitionTable {
+    Mbr(Mbr),
+    Gpt(Gpt),
+}
+
+impl PartitionTable {
+    /// Reads the partition table from the given device. If the device has a
+    /// protective MBR, the GPT is read, otherwise the MBR is returned.
+    pub fn read<D>(device: &D) -> Result<Self>
+    where
+        D: BlockDevice,
+    {
+        let mbr = Mbr::read(device)?;
+        if mbr.is_protective() {
+            Ok(Self::Gpt(Gpt::read(device)?))
+        } else {
+            Ok(Self::Mbr(mbr))
+        }
+    }
+
+    /// The number of used partition entries in this table.
+    pub fn len(&self) -> usize {
+        match self {
+            PartitionTable::Mbr(mbr) => mbr.partitions.len(),
+            PartitionTable::Gpt(gpt) => gpt.partitions.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the first block and the number of blocks of the partition
+    /// with the given index, or `None` if there is no such partition or its
+    /// number of blocks doesn't fit into a `u64`.
+    pub fn bounds(&self, index: usize) -> Option<(u64, u64)> {
+        match self {
+            PartitionTable::Mbr(mbr) => mbr
+                .partitions
+                .get(index)
+                .map(|p| (p.start_lba as u64, p.sector_count as u64)),
+            PartitionTable::Gpt(gpt) => gpt.partitions.get(index).and_then(|p| {
+                let count = p.last_lba.checked_sub(p.first_lba)?.checked_add(1)?;
+                Some((p.first_lba, count))
+            }),
+        }
+    }
+
+    /// Opens the partition with the given index as a [`SubDevice`] of the given
+    /// device, which should be the device that this table was read from.
+    ///
+    /// Returns [`Error::NotFound`] if there is no partition with that index, and
+    /// [`Error::IncoherentData`] if the partition bounds are invalid or the
+    /// partition doesn't fit on the device.
+    pub fn open<D>(&self, index: usize, device: D) -> Result<SubDevice<D>>
+    where
+        D: BlockDevice,
+    {
+        if index >= self.len() {
+            return Err(Error::NotFound);
+        }
+        let (start, block_count) = self.bounds(index).ok_or(Error::IncoherentData)?;
+        SubDevice::new(device, start, block_count).map_err(|_| Error::IncoherentData)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::block::memory::MemoryBlockDevice;
+    use crate::io::block::partition::gpt::tests::image;
+    use crate::io::block::partition::PartitionTable;
+    use crate::io::block::BlockDevice;
+    use crate::io::Error;
+
+    #[test]
+    fn test_partition_table_gpt() {
+        let device = MemoryBlockDevice::from_image(image(512, 16, &[(3, 5), (6, 13)]), 512);
+        let table = PartitionTable::read(&device).unwrap();
+        assert!(matches!(table, PartitionTable::Gpt(_)));
+        assert_eq!(2, table.len());
+        assert_eq!(Some((3, 3)), table.bounds(0));
+        assert_eq!(Some((6, 8)), table.bounds(1));
+        assert_eq!(None, table.bounds(2));
+    }
+
+    #[test]
+    fn test_partition_open() {
+        let device = MemoryBlockDevice::from_image(image(512, 16, &[(3, 5), (6, 13)]), 512);
+        let table = PartitionTable::read(&device).unwrap();
+        assert!(matches!(table.open(2, device), Err(Error::NotFound)));
+
+        let device = MemoryBlockDevice::from_image(image(512, 16, &[(3, 5), (6, 13)]), 512);
+        let mut partition = table.open(0, device).unwrap();
+        assert_eq!(3, partition.block_count());
+        partition.write_block(2, &[7_u8; 512]).unwrap();
+        assert_eq!(
+            Err(Error::NoSuchBlock),
+            partition.write_block(3, &[7_u8; 512])
+        );
+        assert_eq!(
+            Err(Error::NoSuchBlock),
+            partition.read_block(3, &mut [0_u8; 512])
+        );
+
+        let image = partition.into_inner().into_inner();
+        assert_eq!([7_u8; 512], image[5 * 512..6 * 512]);
+        assert_eq!([0_u8; 512], image[6 * 512..7 * 512]);
+    }
+
+    #[test]
+    fn test_partition_does_not_fit() {
+        let device = MemoryBlockDevice::from_image(image(512, 16, &[(3, 16)]), 512);
+        let table = PartitionTable::read(&device).unwrap();
+        assert!(matches!(table.open(0, device), Err(Error::IncoherentData)));
+    }
+
+    #[test]
+    fn test_partition_invalid_bounds() {
+        let device = MemoryBlockDevice::from_image(image(512, 16, &[(3, 5), (6, 13)]), 512);
+        let PartitionTable::Gpt(mut gpt) = PartitionTable::read(&device).unwrap() else {
+            panic!("expected a GPT");
+        };
+        (gpt.partitions[0].first_lba, gpt.partitions[0].last_lba) = (0, u64::MAX);
+        (gpt.partitions[1].first_lba, gpt.partitions[1].last_lba) = (13, 6);
+        let table = PartitionTable::Gpt(gpt);
+        assert_eq!(None, table.bounds(0));
+        assert_eq!(None, table.bounds(1));
+        assert!(matches!(table.open(1, device), Err(Error::IncoherentData)));
+    }
+}
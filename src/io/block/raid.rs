@@ -0,0 +1,600 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use spin::{Mutex, MutexGuard};
+
+use crate::io::block::BlockDevice;
+use crate::io::{Error, Result};
+
+/// Returns the common block size of the given devices, or [`Error::InvalidArgument`]
+/// if there are no devices or their block sizes differ.
+pub(super) fn common_block_size<D>(members: &[D]) -> Result<usize>
+where
+    D: BlockDevice,
+{
+    let block_size = members.first().ok_or(Error::InvalidArgument)?.block_size();
+    if members.iter().any(|m| m.block_size() != block_size) {
+        return Err(Error::InvalidArgument);
+    }
+    Ok(block_size)
+}
+
+/// A [`BlockDevice`] that keeps a full copy of its data on every member (RAID 1).
+///
+/// Writes go to all members. By default, a read reads the block from every
+/// member, returns the copy of the first member that can be read, and rewrites
+/// the copies of the other members if they differ, for example after a crash
+/// in the middle of a write. With [`Mirror::set_verify_reads`], reads can
+/// instead be spread over the members in turn, reading only a single copy, and
+/// differing copies are only found and repaired with [`Mirror::scrub`].
+/// The device has as many blocks as its smallest member.
+///
+/// A member that returns an error is marked as failed, and is no longer used,
+/// so the mirror keeps working in a degraded mode as long as one member is left.
+/// This is permanent, even if the error was transient, because the member may
+/// have missed writes in the meantime. A failed read is retried on the next
+/// member. Once every member has failed, reads fail with [`Error::ReadError`]
+/// and writes with [`Error::WriteError`]. A failed member can be swapped for a
+/// new device with [`Mirror::replace`], which copies the data onto it.
+///
+/// ```
+/// # use kstd::io::block::BlockDevice;
+/// # use kstd::io::block::memory::MemoryBlockDevice;
+/// # use kstd::io::block::raid::Mirror;
+/// let mut mirror = Mirror::new(vec![
+///     MemoryBlockDevice::new(512, 8),
+///     MemoryBlockDevice::new(512, 8),
+/// ])
+/// .unwrap();
+/// mirror.write_block(1, &[1_u8; 512]).unwrap();
+///
+/// let members = mirror.into_inner();
+/// assert_eq!(members[0].get_ref(), members[1].get_ref());
+/// ```
+pub struct Mirror<D>
+where
+    D: BlockDevice,
+{
+    /// The members are locked, so that a read can repair a differing copy.
+    /// At most one member is locked at a time.
+    members: Vec<Mutex<D>>,
+    failed: Vec<AtomicBool>,
+    block_size: usize,
+    block_count: usize,
+    verify_reads: bool,
+    /// The member that serves the next read, if reads are not verified.
+    next: AtomicUsize,
+}
+
+impl<D> Mirror<D>
+where
+    D: BlockDevice,
+{
+    /// Creates a mirror from the given devices, which must all have the same
+    /// block size. The devices are expected to hold the same data already,
+    /// use [`Mirror::scrub`] to synchronize them otherwise.
+    pub fn new(members: Vec<D>) -> Result<Self> {
+        let block_size = common_block_size(&members)?;
+        let block_count = members.iter().map(D::block_count).min().unwrap();
+        Ok(Self {
+            failed: members.iter().map(|_| AtomicBool::new(false)).collect(),
+            members: members.into_iter().map(Mutex::new).collect(),
+            block_size,
+            block_count,
+            verify_reads: true,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    /// Sets whether reads compare the copies of all members and repair those
+    /// that differ, which is the default. Otherwise, every read only reads the
+    /// copy of a single member.
+    pub fn set_verify_reads(&mut self, verify_reads: bool) {
+        self.verify_reads = verify_reads;
+    }
+
+    /// Locks and returns the member with the given index.
+    pub fn member(&self, index: usize) -> MutexGuard<'_, D> {
+        self.members[index].lock()
+    }
+
+    pub fn into_inner(self) -> Vec<D> {
+        self.members.into_iter().map(Mutex::into_inner).collect()
+    }
+
+    /// Whether the member with the given index was marked as failed.
+    pub fn is_failed(&self, index: usize) -> bool {
+        self.failed[index].load(Ordering::Relaxed)
+    }
+
+    /// Whether any member was marked as failed.
+    pub fn is_degraded(&self) -> bool {
+        (0..self.members.len()).any(|i| self.is_failed(i))
+    }
+
+    fn fail(&self, index: usize) {
+        self.failed[index].store(true, Ordering::Relaxed);
+    }
+
+    /// Checks the arguments of a read or write before it is passed on to the
+    /// members, so that an invalid call is not mistaken for a failed member.
+    fn check_args(&self, block: u64, len: usize) -> Result<()> {
+        if block >= self.block_count as u64 {
+            return Err(Error::NoSuchBlock);
+        }
+        if len < self.block_size {
+            return Err(Error::BufferTooSmall);
+        }
+        Ok(())
+    }
+
+    /// Copies the data of the mirror onto a new device, replaces the member with
+    /// the given index with it, and returns the old member. The new device must
+    /// have the block size of the mirror, and at least as many blocks.
+    ///
+    /// If copying fails, the mirror is left unchanged and the new device is
+    /// dropped.
+    pub fn replace(&mut self, index: usize, mut device: D) -> Result<D> {
+        if index >= self.members.len()
+            || device.block_size() != self.block_size
+            || device.block_count() < self.block_count
+        {
+            return Err(Error::InvalidArgument);
+        }
+        let mut data = vec![0_u8; self.block_size];
+        for block in 0..self.block_count as u64 {
+            let _ = self.read_block(block, &mut data)?;
+            let _ = device.write_block(block, &data)?;
+        }
+
+        let old = core::mem::replace(&mut self.members[index], Mutex::new(device));
+        self.failed[index].store(false, Ordering::Relaxed);
+        Ok(old.into_inner())
+    }
+
+    /// Compares every block on all members that haven't failed, and overwrites
+    /// copies that differ from the copy on the first member. Members that return
+    /// an error are marked as failed. Returns the number of blocks that were
+    /// repaired, counting every repaired copy.
+    pub fn scrub(&self) -> Result<usize> {
+        let mut expected = vec![0_u8; self.block_size];
+        let mut repaired = 0;
+        for block in 0..self.block_count as u64 {
+            let Some(authority) = self.read_from_any(0, block, &mut expected)? else {
+                return Err(Error::ReadError);
+            };
+            repaired += self.repair(block, authority, &expected);
+        }
+        Ok(repaired)
+    }
+
+    /// Reads the given block from the first member, starting at the given index,
+    /// that hasn't failed and returns it without an error. Returns the index of
+    /// that member, or `None` if no member could be read.
+    fn read_from_any(&self, first: usize, block: u64, buffer: &mut [u8]) -> Result<Option<usize>> {
+        let count = self.members.len();
+        let mut last_error = None;
+        for index in (first..count).chain(0..first) {
+            if self.is_failed(index) {
+                continue;
+            }
+            match self.members[index]
+                .lock()
+                .read_block(block, &mut &mut *buffer)
+            {
+                Ok(_) => return Ok(Some(index)),
+                Err(e) => {
+                    self.fail(index);
+                    last_error = Some(e);
+                }
+            }
+        }
+        match last_error {
+            Some(e) => Err(e),
+            None => Ok(None),
+        }
+    }
+
+    /// Compares the copies of the given block on all members after the given
+    /// one with the expected data, and overwrites those that differ. Members
+    /// that return an error are marked as failed. Returns the number of
+    /// repaired copies.
+    fn repair(&self, block: u64, authority: usize, expected: &[u8]) -> usize {
+        let mut actual = vec![0_u8; self.block_size];
+        let mut repaired = 0;
+        for index in authority + 1..self.members.len() {
+            if self.is_failed(index) {
+                continue;
+            }
+            let mut member = self.members[index].lock();
+            if member.read_block(block, &mut actual).is_err() {
+                self.fail(index);
+                continue;
+            }
+            if actual != expected {
+                match member.write_block(block, &expected) {
+                    Ok(_) => repaired += 1,
+                    Err(_) => self.fail(index),
+                }
+            }
+        }
+        repaired
+    }
+}
+
+impl<D> BlockDevice for Mirror<D>
+where
+    D: BlockDevice,
+{
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn block_count(&self) -> usize {
+        self.block_count
+    }
+
+    fn read_block(&self, block: u64, buf: &mut dyn AsMut<[u8]>) -> Result<usize> {
+        let buffer = buf.as_mut();
+        self.check_args(block, buffer.len())?;
+        let buffer = &mut buffer[..self.block_size];
+        let first = if self.verify_reads {
+            0
+        } else {
+            self.next.fetch_add(1, Ordering::Relaxed) % self.members.len()
+        };
+        // every member has failed
+        let authority = self
+            .read_from_any(first, block, buffer)?
+            .ok_or(Error::ReadError)?;
+        if self.verify_reads {
+            let _ = self.repair(block, authority, buffer);
+        }
+        Ok(self.block_size)
+    }
+
+    fn write_block(&mut self, block: u64, buf: &dyn AsRef<[u8]>) -> Result<usize> {
+        let buffer = buf.as_ref();
+        self.check_args(block, buffer.len())?;
+        let buffer = &buffer[..self.block_size];
+        let mut written = false;
+        // every member has failed
+        let mut last_error = Error::WriteError;
+        for index in 0..self.members.len() {
+            if self.is_failed(index) {
+                continue;
+            }
+            match self.members[index].get_mut().write_block(block, &buffer) {
+                Ok(_) => written = true,
+                Err(e) => {
+                    self.fail(index);
+                    last_error = e;
+                }
+            }
+        }
+        if written {
+            Ok(self.block_size)
+        } else {
+            Err(last_error)
+        }
+    }
+}
+
+/// A [`BlockDevice`] that distributes its blocks over its members (RAID 0).
+///
+/// The blocks are grouped into chunks of a fixed number of blocks, and
+/// consecutive chunks are placed on consecutive members, so that large
+/// transfers are spread over all members. The device has as many blocks as
+/// fit onto its smallest member, times the number of members.
+///
+/// There is no redundancy. If a member returns an error, operations on the
+/// blocks of that member fail with that error, while the blocks on the other
+/// members remain accessible.
+pub struct Stripe<D>
+where
+    D: BlockDevice,
+{
+    members: Vec<D>,
+    block_size: usize,
+    block_count: usize,
+    chunk_blocks: usize,
+}
+
+impl<D> Stripe<D>
+where
+    D: BlockDevice,
+{
+    /// Creates a stripe set from the given devices, which must all have the
+    /// same block size, with chunks of the given number of blocks.
+    pub fn new(members: Vec<D>, chunk_blocks: usize) -> Result<Self> {
+        let block_size = common_block_size(&members)?;
+        if chunk_blocks == 0 {
+            return Err(Error::InvalidArgument);
+        }
+        let smallest = members.iter().map(D::block_count).min().unwrap();
+        let block_count = smallest / chunk_blocks * chunk_blocks * members.len();
+        Ok(Self {
+            members,
+            block_size,
+            block_count,
+            chunk_blocks,
+        })
+    }
+
+    pub fn members(&self) -> &[D] {
+        &self.members
+    }
+
+    pub fn into_inner(self) -> Vec<D> {
+        self.members
+    }
+
+    pub fn chunk_blocks(&self) -> usize {
+        self.chunk_blocks
+    }
+
+    /// Returns the index of the member that holds the given block, and
+    /// the number of the block on that member.
+    fn locate(&self, block: u64) -> Result<(usize, u64)> {
+        if block >= self.block_count as u64 {
+            return Err(Error::NoSuchBlock);
+        }
+        let chunk_blocks = self.chunk_blocks as u64;
+        let count = self.members.len() as u64;
+        let chunk = block / chunk_blocks;
+        let member = (chunk % count) as usize;
+        let member_block = chunk / count * chunk_blocks + block % chunk_blocks;
+        Ok((member, member_block))
+    }
+}
+
+impl<D> BlockDevice for Stripe<D>
+where
+    D: BlockDevice,
+{
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn block_count(&self) -> usize {
+        self.block_count
+    }
+
+    fn read_block(&self, block: u64, buf: &mut dyn AsMut<[u8]>) -> Result<usize> {
+        let (member, member_block) = self.locate(block)?;
+        self.members[member].read_block(member_block, buf)
+    }
+
+    fn write_block(&mut self, block: u64, buf: &dyn AsRef<[u8]>) -> Result<usize> {
+        let (member, member_block) = self.locate(block)?;
+        self.members[member].write_block(member_block, buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use crate::io::block::faulty::{Fault, FaultyDevice, Operation, Trigger};
+    use crate::io::block::memory::MemoryBlockDevice;
+    use crate::io::block::raid::{Mirror, Stripe};
+    use crate::io::block::BlockDevice;
+    use crate::io::{Error, ReadAt, WriteAt};
+
+    fn faulty(block_count: usize) -> FaultyDevice<MemoryBlockDevice> {
+        FaultyDevice::new(MemoryBlockDevice::new(4, block_count))
+    }
+
+    #[test]
+    fn test_mirror_read_write() {
+        let mut mirror = Mirror::new(vec![faulty(8), faulty(6), faulty(8)]).unwrap();
+        assert_eq!(6, mirror.block_count());
+        let data: Vec<u8> = (0..24).collect();
+        mirror.write_at(0, &data).unwrap();
+
+        let mut read = vec![0_u8; 24];
+        assert_eq!(Ok(24), mirror.read_at(0, &mut read));
+        assert_eq!(data, read);
+        // reads compare the copies of all members
+        assert!((0..3).all(|i| mirror.member(i).reads() == 6));
+        for member in mirror.into_inner() {
+            assert_eq!(data, member.get_ref().get_ref()[..24]);
+        }
+    }
+
+    #[test]
+    fn test_mirror_degraded() {
+        let mut broken = faulty(4);
+        broken.inject(
+            Operation::Read,
+            Trigger::Block(2),
+            Fault::Fail(Error::IncoherentData),
+        );
+        let mut mirror = Mirror::new(vec![broken, faulty(4)]).unwrap();
+        mirror.write_block(2, &[5_u8; 4]).unwrap();
+
+        let mut data = [0_u8; 4];
+        assert_eq!(Ok(4), mirror.read_block(2, &mut data));
+        assert_eq!(Ok(4), mirror.read_block(2, &mut data));
+        assert_eq!([5_u8; 4], data);
+        assert!(mirror.is_failed(0));
+        assert!(!mirror.is_failed(1));
+
+        // the failed member no longer receives writes
+        mirror.write_block(3, &[6_u8; 4]).unwrap();
+        assert_eq!([0_u8; 4], mirror.member(0).get_ref().get_ref()[12..16]);
+
+        let old = mirror.replace(0, faulty(4)).unwrap();
+        assert_eq!(1, old.injected());
+        assert!(!mirror.is_degraded());
+        assert_eq!([6_u8; 4], mirror.member(0).get_ref().get_ref()[12..16]);
+    }
+
+    #[test]
+    fn test_mirror_all_failed() {
+        let mut first = faulty(4);
+        first.inject(
+            Operation::Write,
+            Trigger::Nth(0),
+            Fault::Fail(Error::WriteError),
+        );
+        let mut second = faulty(4);
+        second.inject(
+            Operation::Write,
+            Trigger::Nth(0),
+            Fault::Fail(Error::WriteError),
+        );
+        let mut mirror = Mirror::new(vec![first, second]).unwrap();
+        assert_eq!(Err(Error::WriteError), mirror.write_block(0, &[1_u8; 4]));
+        assert_eq!(Err(Error::ReadError), mirror.read_block(0, &mut [0_u8; 4]));
+        assert_eq!(Err(Error::WriteError), mirror.write_block(1, &[1_u8; 4]));
+        assert_eq!(Err(Error::ReadError), mirror.scrub());
+    }
+
+    #[test]
+    fn test_mirror_failed_replace_keeps_member() {
+        let mut mirror = Mirror::new(vec![faulty(4), faulty(4)]).unwrap();
+        mirror.write_block(2, &[3_u8; 4]).unwrap();
+
+        let mut replacement = faulty(4);
+        replacement.inject(
+            Operation::Write,
+            Trigger::Block(2),
+            Fault::Fail(Error::WriteError),
+        );
+        assert_eq!(
+            Err(Error::WriteError),
+            mirror.replace(1, replacement).map(|_| ())
+        );
+        assert!(!mirror.is_degraded());
+        assert_eq!(0, mirror.member(1).injected());
+        assert_eq!([3_u8; 4], mirror.member(1).get_ref().get_ref()[8..12]);
+    }
+
+    #[test]
+    fn test_mirror_invalid_arguments_keep_members() {
+        let mut mirror = Mirror::new(vec![faulty(4), faulty(4)]).unwrap();
+        mirror.write_block(0, &[1_u8; 4]).unwrap();
+        assert_eq!(
+            Err(Error::BufferTooSmall),
+            mirror.read_block(0, &mut [0_u8; 2])
+        );
+        assert_eq!(
+            Err(Error::BufferTooSmall),
+            mirror.write_block(0, &[0_u8; 2])
+        );
+        assert_eq!(
+            Err(Error::NoSuchBlock),
+            mirror.read_block(4, &mut [0_u8; 4])
+        );
+        assert!(!mirror.is_degraded());
+
+        let mut data = [0_u8; 4];
+        assert_eq!(Ok(4), mirror.read_block(0, &mut data));
+        assert_eq!([1_u8; 4], data);
+    }
+
+    #[test]
+    fn test_mirror_read_repairs() {
+        let mut mirror = Mirror::new(vec![faulty(4), faulty(4)]).unwrap();
+        mirror.write_block(1, &[1_u8; 4]).unwrap();
+        mirror.members[1]
+            .get_mut()
+            .get_mut()
+            .write_block(1, &[2_u8; 4])
+            .unwrap();
+
+        // without verification, every read only reads a single copy
+        mirror.set_verify_reads(false);
+        let mut data = [0_u8; 4];
+        mirror.read_block(1, &mut data).unwrap();
+        mirror.read_block(1, &mut data).unwrap();
+        assert_eq!([2_u8; 4], data);
+        assert_eq!(1, mirror.member(0).reads());
+
+        mirror.set_verify_reads(true);
+        mirror.read_block(1, &mut data).unwrap();
+        assert_eq!([1_u8; 4], data);
+        assert_eq!([1_u8; 4], mirror.member(1).get_ref().get_ref()[4..8]);
+        assert_eq!(Ok(0), mirror.scrub());
+    }
+
+    #[test]
+    fn test_mirror_scrub() {
+        let mut mirror = Mirror::new(vec![faulty(4), faulty(4), faulty(4)]).unwrap();
+        mirror.write_at(0, &[1_u8; 16]).unwrap();
+        mirror.members[1]
+            .get_mut()
+            .get_mut()
+            .write_block(1, &[2_u8; 4])
+            .unwrap();
+        mirror.members[2]
+            .get_mut()
+            .get_mut()
+            .write_block(1, &[2_u8; 4])
+            .unwrap();
+        mirror.members[2]
+            .get_mut()
+            .get_mut()
+            .write_block(3, &[2_u8; 4])
+            .unwrap();
+
+        assert_eq!(Ok(3), mirror.scrub());
+        assert_eq!(Ok(0), mirror.scrub());
+        for member in mirror.into_inner() {
+            assert_eq!([1_u8; 16], member.get_ref().get_ref()[..]);
+        }
+    }
+
+    #[test]
+    fn test_mirror_invalid() {
+        assert!(Mirror::<MemoryBlockDevice>::new(vec![]).is_err());
+        assert!(Mirror::new(vec![
+            MemoryBlockDevice::new(4, 4),
+            MemoryBlockDevice::new(8, 4)
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_stripe_layout() {
+        let members = vec![
+            MemoryBlockDevice::new(1, 7),
+            MemoryBlockDevice::new(1, 5),
+            MemoryBlockDevice::new(1, 6),
+        ];
+        let mut stripe = Stripe::new(members, 2).unwrap();
+        // two chunks of two blocks fit onto the smallest member
+        assert_eq!(12, stripe.block_count());
+        let data: Vec<u8> = (0..12).collect();
+        stripe.write_at(0, &data).unwrap();
+        assert_eq!(Err(Error::NoSuchBlock), stripe.write_block(12, &[0_u8; 1]));
+
+        let mut read = vec![0_u8; 12];
+        assert_eq!(Ok(12), stripe.read_at(0, &mut read));
+        assert_eq!(data, read);
+
+        let members = stripe.into_inner();
+        assert_eq!([0, 1, 6, 7], members[0].get_ref()[..4]);
+        assert_eq!([2, 3, 8, 9], members[1].get_ref()[..4]);
+        assert_eq!([4, 5, 10, 11], members[2].get_ref()[..4]);
+    }
+
+    #[test]
+    fn test_stripe_member_failure() {
+        let mut broken = faulty(4);
+        broken.inject(
+            Operation::Read,
+            Trigger::Block(0),
+            Fault::Fail(Error::IncoherentData),
+        );
+        let stripe = Stripe::new(vec![faulty(4), broken], 1).unwrap();
+        let mut data = [0_u8; 4];
+        assert_eq!(Ok(4), stripe.read_block(0, &mut data));
+        assert_eq!(Err(Error::IncoherentData), stripe.read_block(1, &mut data));
+        assert_eq!(Ok(4), stripe.read_block(2, &mut data));
+        assert_eq!(Ok(4), stripe.read_block(3, &mut data));
+    }
+}
@@ -0,0 +1,294 @@
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use crate::io::block::cow::CowBlockDevice;
+use crate::io::block::BlockDevice;
+use crate::io::{Error, Result};
+
+#[derive(Clone)]
+struct Layer {
+    name: String,
+    blocks: Arc<BTreeMap<u64, Vec<u8>>>,
+}
+
+/// A read-only view of a [`SnapshotDevice`] at the time a snapshot was taken.
+///
+/// A snapshot shares its data with the device it was taken from, so it is
+/// cheap to obtain, and it stays valid even if the snapshot is merged or
+/// rolled back on the device afterwards. Writing to a snapshot fails with
+/// [`Error::NotImplemented`].
+pub struct Snapshot<D>
+where
+    D: BlockDevice + Send + Sync,
+{
+    base: Arc<D>,
+    /// The layers of this snapshot, oldest first.
+    layers: Vec<Layer>,
+}
+
+impl<D> Clone for Snapshot<D>
+where
+    D: BlockDevice + Send + Sync,
+{
+    fn clone(&self) -> Self {
+        Self {
+            base: self.base.clone(),
+            layers: self.layers.clone(),
+        }
+    }
+}
+
+impl<D> BlockDevice for Snapshot<D>
+where
+    D: BlockDevice + Send + Sync,
+{
+    fn block_size(&self) -> usize {
+        self.base.block_size()
+    }
+
+    fn block_count(&self) -> usize {
+        self.base.block_count()
+    }
+
+    fn read_block(&self, block: u64, buf: &mut dyn AsMut<[u8]>) -> Result<usize> {
+        let buffer = buf.as_mut();
+        let block_size = self.block_size();
+        if buffer.len() < block_size {
+            return Err(Error::BufferTooSmall);
+        }
+
+        for layer in self.layers.iter().rev() {
+            if let Some(b) = layer.blocks.get(&block) {
+                buffer[0..block_size].copy_from_slice(b);
+                return Ok(block_size);
+            }
+        }
+
+        self.base.read_block(block, &mut &mut buffer[0..block_size])
+    }
+
+    fn write_block(&mut self, _: u64, _: &dyn AsRef<[u8]>) -> Result<usize> {
+        Err(Error::NotImplemented)
+    }
+}
+
+/// A block device that supports named snapshots of its state.
+///
+/// All writes are kept in memory on top of the base device, which is never
+/// written to. Taking a snapshot freezes all writes up to that point into a
+/// new layer, and the state of the device at that time can be read with
+/// [`SnapshotDevice::snapshot`] without copying the device.
+///
+/// ```rust
+/// use kstd::io::block::one::OneDevice;
+/// use kstd::io::block::snapshot::SnapshotDevice;
+/// use kstd::io::block::BlockDevice;
+///
+/// let mut device = SnapshotDevice::new(OneDevice::new(512, 8));
+/// device.take_snapshot("before").unwrap();
+/// device.write_block(0, &[2_u8; 512]).unwrap();
+///
+/// let before = device.snapshot("before").unwrap();
+/// let mut data = [0_u8; 512];
+/// before.read_block(0, &mut data).unwrap();
+/// assert_eq!([1_u8; 512], data);
+/// device.read_block(0, &mut data).unwrap();
+/// assert_eq!([2_u8; 512], data);
+/// ```
+pub struct SnapshotDevice<D>
+where
+    D: BlockDevice + Send + Sync,
+{
+    live: CowBlockDevice<Snapshot<D>>,
+}
+
+impl<D> SnapshotDevice<D>
+where
+    D: BlockDevice + Send + Sync,
+{
+    pub fn new(base: D) -> Self {
+        Self {
+            live: CowBlockDevice::new(Snapshot {
+                base: Arc::new(base),
+                layers: Vec::new(),
+            }),
+        }
+    }
+
+    /// Freezes all writes up to now into a new snapshot with the given name.
+    /// Fails with [`Error::ExistsButShouldNot`] if a snapshot with that name
+    /// already exists.
+    pub fn take_snapshot(&mut self, name: &str) -> Result<()> {
+        if self.position(name).is_some() {
+            return Err(Error::ExistsButShouldNot);
+        }
+        let blocks = self.live.take_blocks();
+        self.live.get_mut().layers.push(Layer {
+            name: name.to_string(),
+            blocks: Arc::new(blocks),
+        });
+        Ok(())
+    }
+
+    /// Returns a read-only device with the state at the time the snapshot
+    /// with the given name was taken.
+    pub fn snapshot(&self, name: &str) -> Result<Snapshot<D>> {
+        let pos = self.position(name).ok_or(Error::NotFound)?;
+        let current = self.live.get_ref();
+        Ok(Snapshot {
+            base: current.base.clone(),
+            layers: current.layers[..=pos].to_vec(),
+        })
+    }
+
+    /// Returns the names of all snapshots, oldest first.
+    pub fn snapshots(&self) -> impl Iterator<Item = &str> {
+        self.live.get_ref().layers.iter().map(|l| l.name.as_str())
+    }
+
+    /// Removes the snapshot with the given name. Its changes are merged
+    /// into the next newer layer, so the state of all other snapshots, as
+    /// well as the current state of the device, does not change.
+    pub fn merge(&mut self, name: &str) -> Result<()> {
+        let pos = self.position(name).ok_or(Error::NotFound)?;
+        let layer = self.live.get_mut().layers.remove(pos);
+        let blocks = Arc::try_unwrap(layer.blocks).unwrap_or_else(|b| (*b).clone());
+
+        let next = match self.live.get_mut().layers.get_mut(pos) {
+            Some(next) => Arc::make_mut(&mut next.blocks),
+            None => self.live.blocks_mut(),
+        };
+        for (num, data) in blocks {
+            // newer writes take precedence
+            next.entry(num).or_insert(data);
+        }
+        Ok(())
+    }
+
+    /// Restores the state at the time the snapshot with the given name was
+    /// taken. All writes since then, including all newer snapshots, are
+    /// discarded. The snapshot itself is kept.
+    pub fn rollback(&mut self, name: &str) -> Result<()> {
+        let pos = self.position(name).ok_or(Error::NotFound)?;
+        self.live.get_mut().layers.truncate(pos + 1);
+        self.live.discard();
+        Ok(())
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.live
+            .get_ref()
+            .layers
+            .iter()
+            .position(|l| l.name == name)
+    }
+}
+
+impl<D> BlockDevice for SnapshotDevice<D>
+where
+    D: BlockDevice + Send + Sync,
+{
+    fn block_size(&self) -> usize {
+        self.live.block_size()
+    }
+
+    fn block_count(&self) -> usize {
+        self.live.block_count()
+    }
+
+    fn read_block(&self, block: u64, buf: &mut dyn AsMut<[u8]>) -> Result<usize> {
+        self.live.read_block(block, buf)
+    }
+
+    fn write_block(&mut self, block: u64, buf: &dyn AsRef<[u8]>) -> Result<usize> {
+        self.live.write_block(block, buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use crate::io::block::one::OneDevice;
+    use crate::io::block::snapshot::SnapshotDevice;
+    use crate::io::block::BlockDevice;
+    use crate::io::Error;
+
+    fn read(device: &impl BlockDevice, block: u64) -> u8 {
+        let mut data = vec![0_u8; device.block_size()];
+        device.read_block(block, &mut data).unwrap();
+        assert!(data.iter().all(|&b| b == data[0]));
+        data[0]
+    }
+
+    fn setup() -> SnapshotDevice<OneDevice> {
+        let mut device = SnapshotDevice::new(OneDevice::new(16, 8));
+        device.write_block(1, &[2_u8; 16]).unwrap();
+        device.take_snapshot("a").unwrap();
+        device.write_block(1, &[3_u8; 16]).unwrap();
+        device.write_block(2, &[4_u8; 16]).unwrap();
+        device.take_snapshot("b").unwrap();
+        device.write_block(1, &[5_u8; 16]).unwrap();
+        device
+    }
+
+    #[test]
+    fn test_snapshot_read() {
+        let device = setup();
+        assert_eq!(vec!["a", "b"], device.snapshots().collect::<Vec<_>>());
+
+        let a = device.snapshot("a").unwrap();
+        assert_eq!((2, 1), (read(&a, 1), read(&a, 2)));
+        let b = device.snapshot("b").unwrap();
+        assert_eq!((3, 4), (read(&b, 1), read(&b, 2)));
+        assert_eq!((5, 4), (read(&device, 1), read(&device, 2)));
+        assert_eq!(1, read(&device, 3));
+
+        assert!(matches!(device.snapshot("c"), Err(Error::NotFound)));
+    }
+
+    #[test]
+    fn test_snapshot_duplicate_name() {
+        let mut device = setup();
+        assert_eq!(Err(Error::ExistsButShouldNot), device.take_snapshot("a"));
+    }
+
+    #[test]
+    fn test_snapshot_is_read_only() {
+        let device = setup();
+        let mut a = device.snapshot("a").unwrap();
+        assert_eq!(Err(Error::NotImplemented), a.write_block(1, &[0_u8; 16]));
+    }
+
+    #[test]
+    fn test_snapshot_merge() {
+        let mut device = setup();
+        let old_a = device.snapshot("a").unwrap();
+
+        device.merge("a").unwrap();
+        assert_eq!(vec!["b"], device.snapshots().collect::<Vec<_>>());
+        let b = device.snapshot("b").unwrap();
+        assert_eq!((3, 4), (read(&b, 1), read(&b, 2)));
+        // views that were obtained earlier are not affected
+        assert_eq!((2, 1), (read(&old_a, 1), read(&old_a, 2)));
+
+        // merging the newest snapshot merges it into the live layer
+        device.merge("b").unwrap();
+        assert_eq!(0, device.snapshots().count());
+        assert_eq!((5, 4), (read(&device, 1), read(&device, 2)));
+    }
+
+    #[test]
+    fn test_snapshot_rollback() {
+        let mut device = setup();
+        device.rollback("b").unwrap();
+        assert_eq!((3, 4), (read(&device, 1), read(&device, 2)));
+
+        device.rollback("a").unwrap();
+        assert_eq!(vec!["a"], device.snapshots().collect::<Vec<_>>());
+        assert_eq!((2, 1), (read(&device, 1), read(&device, 2)));
+    }
+}
@@ -0,0 +1,183 @@
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use crate::io::block::BlockDevice;
+use crate::io::{Error, Result};
+
+/// A thin provisioned [`BlockDevice`] in memory, that only stores the blocks
+/// that were written.
+///
+/// All other blocks read as zeros, so the device can be much larger than the
+/// memory it occupies. Writing a block of zeros releases its memory again.
+///
+/// ```
+/// # use kstd::io::block::BlockDevice;
+/// # use kstd::io::block::sparse::SparseDevice;
+/// // 2 TiB, of which only a single block is stored
+/// let mut device = SparseDevice::new(512, 1 << 32);
+/// device.write_block(1 << 31, &[1_u8; 512]).unwrap();
+/// assert_eq!(1, device.allocated_blocks());
+/// ```
+pub struct SparseDevice {
+    block_size: usize,
+    block_count: usize,
+    blocks: BTreeMap<u64, Vec<u8>>,
+}
+
+impl SparseDevice {
+    pub fn new(block_size: usize, block_count: usize) -> Self {
+        Self {
+            block_size,
+            block_count,
+            blocks: BTreeMap::new(),
+        }
+    }
+
+    /// The number of blocks that are stored in memory.
+    pub fn allocated_blocks(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Returns the numbers of all blocks that are stored in memory,
+    /// in ascending order.
+    pub fn allocated(&self) -> impl Iterator<Item = u64> + '_ {
+        self.blocks.keys().copied()
+    }
+
+    /// Releases the memory of `count` blocks, starting with the given block,
+    /// so that they read as zeros.
+    pub fn discard(&mut self, start: u64, count: usize) -> Result<()> {
+        let end = self.check_range(start, count)?;
+        let discarded: Vec<u64> = self.blocks.range(start..end).map(|(&num, _)| num).collect();
+        for num in discarded {
+            self.blocks.remove(&num);
+        }
+        Ok(())
+    }
+
+    /// Returns the end of the given range of blocks, or [`Error::NoSuchBlock`]
+    /// if it doesn't fit onto this device.
+    fn check_range(&self, start: u64, count: usize) -> Result<u64> {
+        match start.checked_add(count as u64) {
+            Some(end) if end <= self.block_count as u64 => Ok(end),
+            _ => Err(Error::NoSuchBlock),
+        }
+    }
+}
+
+impl BlockDevice for SparseDevice {
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn block_count(&self) -> usize {
+        self.block_count
+    }
+
+    fn read_block(&self, block: u64, buf: &mut dyn AsMut<[u8]>) -> Result<usize> {
+        self.read_blocks(block, 1, buf)
+    }
+
+    fn write_block(&mut self, block: u64, buf: &dyn AsRef<[u8]>) -> Result<usize> {
+        let buffer = buf.as_ref();
+        let block_size = self.block_size;
+        if buffer.len() < block_size {
+            return Err(Error::BufferTooSmall);
+        }
+        let _ = self.check_range(block, 1)?;
+
+        let data = &buffer[..block_size];
+        if data.iter().all(|&b| b == 0) {
+            self.blocks.remove(&block);
+        } else {
+            match self.blocks.get_mut(&block) {
+                Some(b) => b.copy_from_slice(data),
+                None => {
+                    self.blocks.insert(block, data.to_vec());
+                }
+            }
+        }
+        Ok(block_size)
+    }
+
+    /// Reads the given blocks with a single lookup in the map of stored blocks,
+    /// so that reading a large range that is mostly unallocated is cheap.
+    fn read_blocks(&self, start: u64, count: usize, buf: &mut dyn AsMut<[u8]>) -> Result<usize> {
+        let buffer = buf.as_mut();
+        let block_size = self.block_size;
+        let len = count * block_size;
+        if buffer.len() < len {
+            return Err(Error::BufferTooSmall);
+        }
+        let end = self.check_range(start, count)?;
+
+        buffer[..len].fill(0);
+        for (&num, data) in self.blocks.range(start..end) {
+            let offset = (num - start) as usize * block_size;
+            buffer[offset..offset + block_size].copy_from_slice(data);
+        }
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    use crate::io::block::sparse::SparseDevice;
+    use crate::io::block::BlockDevice;
+    use crate::io::{Error, ReadAt, WriteAt};
+
+    #[test]
+    fn test_sparse_read_write() {
+        // 4 TiB with 4 KiB blocks
+        let mut device = SparseDevice::new(4096, 1 << 30);
+        let offset = (1_u64 << 42) - 6000;
+        let data: Vec<u8> = (0..6000).map(|i| i as u8).collect();
+        device.write_at(offset, &data).unwrap();
+        assert_eq!(2, device.allocated_blocks());
+
+        let mut read = vec![0_u8; 6000];
+        assert_eq!(Ok(6000), device.read_at(offset, &mut read));
+        assert_eq!(data, read);
+
+        let mut block = [1_u8; 4096];
+        assert_eq!(Ok(4096), device.read_block(12345, &mut block));
+        assert_eq!([0_u8; 4096], block);
+        assert_eq!(
+            Err(Error::NoSuchBlock),
+            device.write_block(1 << 30, &[1_u8; 4096])
+        );
+        assert_eq!(
+            Err(Error::NoSuchBlock),
+            device.read_blocks((1 << 30) - 1, 2, &mut [0_u8; 8192])
+        );
+    }
+
+    #[test]
+    fn test_sparse_read_blocks() {
+        let mut device = SparseDevice::new(2, 16);
+        device.write_block(3, &[3_u8; 2]).unwrap();
+        device.write_block(5, &[5_u8; 2]).unwrap();
+        device.write_block(9, &[9_u8; 2]).unwrap();
+
+        let mut data = [0xFF_u8; 8];
+        assert_eq!(Ok(8), device.read_blocks(2, 4, &mut data));
+        assert_eq!([0, 0, 3, 3, 0, 0, 5, 5], data);
+    }
+
+    #[test]
+    fn test_sparse_deallocates() {
+        let mut device = SparseDevice::new(2, 16);
+        for block in 0..8 {
+            device.write_block(block, &[1_u8; 2]).unwrap();
+        }
+        device.write_block(0, &[0_u8; 2]).unwrap();
+        assert_eq!(7, device.allocated_blocks());
+
+        device.discard(2, 3).unwrap();
+        assert_eq!(vec![1, 5, 6, 7], device.allocated().collect::<Vec<_>>());
+        assert_eq!(Err(Error::NoSuchBlock), device.discard(10, 7));
+    }
+}
@@ -0,0 +1,247 @@
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::io::block::BlockDevice;
+use crate::io::{Error, Read, Result, Seek, SeekFrom, Write};
+
+/// A byte stream over a [`BlockDevice`], similar to a [`Cursor`](crate::io::cursor::Cursor)
+/// over a byte slice.
+///
+/// The stream keeps the block at the current position in an internal buffer,
+/// so that small reads, like the ones of [`read_be_u32`](crate::read_be_u32),
+/// don't hit the device every time. Writes go through to the device immediately
+/// and update the buffer, so [`Write::flush`] is a no-op. If you need writes
+/// to be buffered, use a stream over a [`BlockCache`](crate::io::block::cache::BlockCache).
+///
+/// The length of the stream is `block_count * block_size` of the device.
+/// Reads and writes at or beyond the end return 0.
+pub struct BlockDeviceStream<D>
+where
+    D: BlockDevice,
+{
+    inner: D,
+    pos: u64,
+    block_size: usize,
+    buffer: Vec<u8>,
+    /// The block that is currently held in the buffer, if any.
+    buffered_block: Option<u64>,
+}
+
+impl<D> BlockDeviceStream<D>
+where
+    D: BlockDevice,
+{
+    pub fn new(inner: D) -> Self {
+        let block_size = inner.block_size();
+        Self {
+            inner,
+            pos: 0,
+            block_size,
+            buffer: vec![0_u8; block_size],
+            buffered_block: None,
+        }
+    }
+
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    pub const fn get_ref(&self) -> &D {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner device. Writing to the device
+    /// directly invalidates the buffer of this stream.
+    pub fn get_mut(&mut self) -> &mut D {
+        self.buffered_block = None;
+        &mut self.inner
+    }
+
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    pub fn set_position(&mut self, pos: u64) {
+        self.pos = pos;
+    }
+
+    /// The length of this stream in bytes.
+    pub fn len(&self) -> u64 {
+        self.inner.block_count() as u64 * self.block_size as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Loads the given block into the buffer, unless it is already buffered.
+    fn buffer_block(&mut self, block: u64) -> Result<()> {
+        if self.buffered_block != Some(block) {
+            self.buffered_block = None;
+            self.inner.read_block(block, &mut self.buffer)?;
+            self.buffered_block = Some(block);
+        }
+        Ok(())
+    }
+
+    /// Returns the block at the current position, the offset of the current
+    /// position within that block, and the number of bytes that can be accessed
+    /// in that block, given a buffer of the given length.
+    fn locate(&self, len: usize) -> (u64, usize, usize) {
+        let block = self.pos / self.block_size as u64;
+        let relative_offset = (self.pos % self.block_size as u64) as usize;
+        let remaining = (self.len() - self.pos).min((self.block_size - relative_offset) as u64);
+        (block, relative_offset, len.min(remaining as usize))
+    }
+}
+
+impl<D> Seek for BlockDeviceStream<D>
+where
+    D: BlockDevice,
+{
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::End(n) => self.len().checked_add_signed(n),
+            SeekFrom::Current(n) => self.pos.checked_add_signed(n),
+        };
+        match new_pos {
+            Some(p) => {
+                self.pos = p;
+                Ok(p)
+            }
+            None => Err(Error::InvalidOffset),
+        }
+    }
+}
+
+impl<D> Read<u8> for BlockDeviceStream<D>
+where
+    D: BlockDevice,
+{
+    fn read(&mut self, buf: &mut dyn AsMut<[u8]>) -> Result<usize> {
+        let buffer = buf.as_mut();
+        if buffer.is_empty() || self.pos >= self.len() {
+            return Ok(0);
+        }
+
+        let (block, relative_offset, len) = self.locate(buffer.len());
+        self.buffer_block(block)?;
+        buffer[..len].copy_from_slice(&self.buffer[relative_offset..relative_offset + len]);
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+impl<D> Write<u8> for BlockDeviceStream<D>
+where
+    D: BlockDevice,
+{
+    fn write(&mut self, buf: &dyn AsRef<[u8]>) -> Result<usize> {
+        let buffer = buf.as_ref();
+        if buffer.is_empty() || self.pos >= self.len() {
+            return Ok(0);
+        }
+
+        let (block, relative_offset, len) = self.locate(buffer.len());
+        if len == self.block_size {
+            // we overwrite the full block, so there is no need to read it first
+            self.buffered_block = None;
+            self.buffer.copy_from_slice(&buffer[..len]);
+        } else {
+            self.buffer_block(block)?;
+            self.buffer[relative_offset..relative_offset + len].copy_from_slice(&buffer[..len]);
+        }
+        // if the write fails, we don't know what's on the device, so drop the buffer
+        self.buffered_block = None;
+        self.inner.write_block(block, &self.buffer)?;
+        self.buffered_block = Some(block);
+
+        self.pos += len as u64;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use crate::io::block::memory::MemoryBlockDevice;
+    use crate::io::block::stream::BlockDeviceStream;
+    use crate::io::{Read, Result, Seek, SeekFrom, Write};
+    use crate::{read_be_u16, read_be_u32, read_bytes, read_le_u32};
+
+    fn stream() -> BlockDeviceStream<MemoryBlockDevice> {
+        let image: Vec<u8> = (0..35).collect();
+        BlockDeviceStream::new(MemoryBlockDevice::from_image(image, 7))
+    }
+
+    #[test]
+    fn test_stream_read_macros() -> Result<()> {
+        let mut s = stream();
+        assert_eq!(0x00010203, read_be_u32!(s));
+        // crosses the first block boundary
+        assert_eq!(0x07060504, read_le_u32!(s));
+        assert_eq!(0x0809, read_be_u16!(s));
+        assert_eq!(10, s.position());
+        Ok(())
+    }
+
+    #[test]
+    fn test_stream_read_exact_across_blocks() {
+        let mut s = stream();
+        s.seek(SeekFrom::Start(5)).unwrap();
+        let mut data = [0_u8; 20];
+        s.read_exact(&mut data).unwrap();
+        assert_eq!((5..25).collect::<Vec<u8>>(), data);
+    }
+
+    #[test]
+    fn test_stream_read_end() {
+        let mut s = stream();
+        s.seek(SeekFrom::End(-3)).unwrap();
+        let mut data = [0_u8; 5];
+        assert_eq!(Ok(3), s.read(&mut data));
+        assert_eq!([32, 33, 34, 0, 0], data);
+        assert_eq!(Ok(0), s.read(&mut data));
+        assert!(s.read_exact(&mut data).is_err());
+    }
+
+    #[test]
+    fn test_stream_seek() {
+        let mut s = stream();
+        assert_eq!(35, s.len());
+        assert_eq!(Ok(35), s.seek(SeekFrom::End(0)));
+        assert_eq!(Ok(30), s.seek(SeekFrom::Current(-5)));
+        assert_eq!(Ok(32), s.seek(SeekFrom::Current(2)));
+        assert!(s.seek(SeekFrom::End(-36)).is_err());
+        assert!(s.seek(SeekFrom::Current(-33)).is_err());
+        assert_eq!(Ok(32), s.stream_position());
+        assert_eq!(Ok(0), s.rewind());
+    }
+
+    #[test]
+    fn test_stream_write() {
+        let mut s = stream();
+        s.seek(SeekFrom::Start(3)).unwrap();
+        s.write_all(&[100_u8; 13]).unwrap();
+        // reading back from the buffered block sees the write
+        s.seek(SeekFrom::Start(14)).unwrap();
+        let mut data = [0_u8; 3];
+        s.read_exact(&mut data).unwrap();
+        assert_eq!([100, 100, 16], data);
+
+        s.seek(SeekFrom::End(-2)).unwrap();
+        assert!(s.write_all(&[200_u8; 3]).is_err());
+
+        let image = s.into_inner().into_inner();
+        assert_eq!([0, 1, 2], image[..3]);
+        assert_eq!([100_u8; 13], image[3..16]);
+        assert_eq!([16, 17], image[16..18]);
+        assert_eq!([200, 200], image[33..]);
+    }
+}
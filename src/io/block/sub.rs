@@ -0,0 +1,138 @@
+use crate::io::block::BlockDevice;
+use crate::io::{Error, Result};
+
+/// A window over the blocks `[start, start + len)` of another [`BlockDevice`].
+///
+/// Block `0` of the sub-device is block `start` of the inner device, and
+/// access to blocks outside of the window fails with [`Error::NoSuchBlock`].
+/// This is the building block for partitions, reserved areas and similar
+/// regions of a device.
+pub struct SubDevice<D>
+where
+    D: BlockDevice,
+{
+    inner: D,
+    start: u64,
+    len: u64,
+}
+
+impl<D> SubDevice<D>
+where
+    D: BlockDevice,
+{
+    /// Creates a sub-device over `len` blocks of the given device, starting at
+    /// block `start`. Fails with [`Error::NoSuchBlock`] if the window doesn't fit
+    /// on the device.
+    pub fn new(inner: D, start: u64, len: u64) -> Result<Self> {
+        match start.checked_add(len) {
+            Some(end) if end <= inner.block_count() as u64 => Ok(Self { inner, start, len }),
+            _ => Err(Error::NoSuchBlock),
+        }
+    }
+
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    pub const fn get_ref(&self) -> &D {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut D {
+        &mut self.inner
+    }
+
+    /// The first block of this sub-device on the inner device.
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    /// Maps the given number of blocks, starting at the given block,
+    /// to the first of these blocks on the inner device.
+    fn map(&self, block: u64, count: usize) -> Result<u64> {
+        match block.checked_add(count as u64) {
+            Some(end) if block < self.len && end <= self.len => Ok(self.start + block),
+            _ => Err(Error::NoSuchBlock),
+        }
+    }
+}
+
+impl<D> BlockDevice for SubDevice<D>
+where
+    D: BlockDevice,
+{
+    fn block_size(&self) -> usize {
+        self.inner.block_size()
+    }
+
+    fn block_count(&self) -> usize {
+        self.len as usize
+    }
+
+    fn read_block(&self, block: u64, buf: &mut dyn AsMut<[u8]>) -> Result<usize> {
+        self.inner.read_block(self.map(block, 1)?, buf)
+    }
+
+    fn write_block(&mut self, block: u64, buf: &dyn AsRef<[u8]>) -> Result<usize> {
+        let block = self.map(block, 1)?;
+        self.inner.write_block(block, buf)
+    }
+
+    fn read_blocks(&self, start: u64, count: usize, buf: &mut dyn AsMut<[u8]>) -> Result<usize> {
+        self.inner.read_blocks(self.map(start, count)?, count, buf)
+    }
+
+    fn write_blocks(&mut self, start: u64, count: usize, buf: &dyn AsRef<[u8]>) -> Result<usize> {
+        let start = self.map(start, count)?;
+        self.inner.write_blocks(start, count, buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::io::block::memory::MemoryBlockDevice;
+    use crate::io::block::sub::SubDevice;
+    use crate::io::block::BlockDevice;
+    use crate::io::Error;
+
+    #[test]
+    fn test_sub_device_bounds() {
+        assert!(SubDevice::new(MemoryBlockDevice::new(4, 8), 0, 8).is_ok());
+        assert!(SubDevice::new(MemoryBlockDevice::new(4, 8), 8, 0).is_ok());
+        assert!(matches!(
+            SubDevice::new(MemoryBlockDevice::new(4, 8), 5, 4),
+            Err(Error::NoSuchBlock)
+        ));
+        assert!(matches!(
+            SubDevice::new(MemoryBlockDevice::new(4, 8), u64::MAX, 2),
+            Err(Error::NoSuchBlock)
+        ));
+    }
+
+    #[test]
+    fn test_sub_device_remap() {
+        let mut sub = SubDevice::new(MemoryBlockDevice::new(4, 8), 2, 3).unwrap();
+        assert_eq!(3, sub.block_count());
+        assert_eq!(4, sub.block_size());
+        for block in 0..3 {
+            sub.write_block(block, &[block as u8 + 1; 4]).unwrap();
+        }
+        let mut data = [0_u8; 4];
+        sub.read_block(1, &mut data).unwrap();
+        assert_eq!([2_u8; 4], data);
+        assert_eq!(Err(Error::NoSuchBlock), sub.read_block(3, &mut data));
+        assert_eq!(Err(Error::NoSuchBlock), sub.write_block(3, &data));
+        assert_eq!(
+            Err(Error::NoSuchBlock),
+            sub.read_blocks(1, 3, &mut [0_u8; 12])
+        );
+        let mut data = [0_u8; 8];
+        sub.read_blocks(1, 2, &mut data).unwrap();
+        assert_eq!([2, 2, 2, 2, 3, 3, 3, 3], data);
+
+        assert_eq!(
+            [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3],
+            sub.into_inner().into_inner()[..20]
+        );
+    }
+}
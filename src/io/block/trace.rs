@@ -0,0 +1,409 @@
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use spin::Mutex;
+
+use crate::checksum::crc32;
+use crate::io::block::BlockDevice;
+use crate::io::{Error, Result};
+
+/// A call to a [`BlockDevice`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Call {
+    BlockSize,
+    BlockCount,
+    ReadBlock { block: u64 },
+    WriteBlock { block: u64 },
+    ReadBlocks { start: u64, count: usize },
+    WriteBlocks { start: u64, count: usize },
+}
+
+/// How much of the transferred data a [`TracingDevice`] records.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Capture {
+    /// Only the calls and their results.
+    Calls,
+    /// The calls, their results and a CRC-32 of the data.
+    Hashes,
+    /// The calls, their results, a CRC-32 of the data and a copy of the data.
+    /// Reads can only be replayed faithfully if the data was recorded.
+    Data,
+}
+
+/// A single call that was recorded by a [`TracingDevice`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TraceEntry {
+    pub call: Call,
+    /// The length of the buffer that was passed to a read or write, zero otherwise.
+    pub len: usize,
+    /// The result of the call. For [`Call::BlockSize`] and [`Call::BlockCount`],
+    /// this is the returned value.
+    pub result: Result<usize>,
+    /// The CRC-32 of the data that was read or written, if the call succeeded.
+    pub hash: Option<u32>,
+    /// The data that was read or written, if the call succeeded.
+    pub data: Option<Vec<u8>>,
+}
+
+/// A [`BlockDevice`] that records every call to the inner device, together with
+/// its result, in a trace. The trace can be inspected to check how a component
+/// uses its device, or fed into a [`ReplayDevice`] to reproduce a bug without
+/// the original device.
+///
+/// ```
+/// # use kstd::io::block::BlockDevice;
+/// # use kstd::io::block::memory::MemoryBlockDevice;
+/// # use kstd::io::block::trace::{Call, Capture, TracingDevice};
+/// let mut device = TracingDevice::new(MemoryBlockDevice::new(512, 8), Capture::Calls);
+/// device.write_block(3, &[1_u8; 512]).unwrap();
+/// device.read_block(3, &mut [0_u8; 512]).unwrap();
+///
+/// assert_eq!(1, device.count(|call| matches!(call, Call::ReadBlock { .. })));
+/// assert_eq!(Call::WriteBlock { block: 3 }, device.trace()[0].call);
+/// ```
+pub struct TracingDevice<D>
+where
+    D: BlockDevice,
+{
+    inner: D,
+    capture: Capture,
+    entries: Mutex<Vec<TraceEntry>>,
+}
+
+impl<D> TracingDevice<D>
+where
+    D: BlockDevice,
+{
+    pub fn new(inner: D, capture: Capture) -> Self {
+        Self {
+            inner,
+            capture,
+            entries: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn get_ref(&self) -> &D {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut D {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+
+    /// Returns a copy of all entries that were recorded so far, oldest first.
+    pub fn trace(&self) -> Vec<TraceEntry> {
+        self.entries.lock().clone()
+    }
+
+    /// Returns all entries that were recorded so far, and clears the trace.
+    pub fn take_trace(&self) -> Vec<TraceEntry> {
+        core::mem::take(&mut *self.entries.lock())
+    }
+
+    /// Returns the number of recorded calls that match the given predicate.
+    pub fn count(&self, predicate: impl Fn(&Call) -> bool) -> usize {
+        self.entries
+            .lock()
+            .iter()
+            .filter(|entry| predicate(&entry.call))
+            .count()
+    }
+
+    fn record(&self, call: Call, len: usize, result: Result<usize>, data: Option<&[u8]>) {
+        let data = data.filter(|_| result.is_ok());
+        let (hash, data) = match self.capture {
+            Capture::Calls => (None, None),
+            Capture::Hashes => (data.map(crc32), None),
+            Capture::Data => (data.map(crc32), data.map(|d| d.to_vec())),
+        };
+        self.entries.lock().push(TraceEntry {
+            call,
+            len,
+            result,
+            hash,
+            data,
+        });
+    }
+}
+
+impl<D> BlockDevice for TracingDevice<D>
+where
+    D: BlockDevice,
+{
+    fn block_size(&self) -> usize {
+        let block_size = self.inner.block_size();
+        self.record(Call::BlockSize, 0, Ok(block_size), None);
+        block_size
+    }
+
+    fn block_count(&self) -> usize {
+        let block_count = self.inner.block_count();
+        self.record(Call::BlockCount, 0, Ok(block_count), None);
+        block_count
+    }
+
+    fn read_block(&self, block: u64, buf: &mut dyn AsMut<[u8]>) -> Result<usize> {
+        let buffer = buf.as_mut();
+        let result = self.inner.read_block(block, &mut &mut *buffer);
+        let len = *result.as_ref().unwrap_or(&0);
+        self.record(
+            Call::ReadBlock { block },
+            buffer.len(),
+            result,
+            Some(&buffer[..len.min(buffer.len())]),
+        );
+        result
+    }
+
+    fn write_block(&mut self, block: u64, buf: &dyn AsRef<[u8]>) -> Result<usize> {
+        let buffer = buf.as_ref();
+        let result = self.inner.write_block(block, buf);
+        let len = *result.as_ref().unwrap_or(&0);
+        self.record(
+            Call::WriteBlock { block },
+            buffer.len(),
+            result,
+            Some(&buffer[..len.min(buffer.len())]),
+        );
+        result
+    }
+
+    fn read_blocks(&self, start: u64, count: usize, buf: &mut dyn AsMut<[u8]>) -> Result<usize> {
+        let buffer = buf.as_mut();
+        let result = self.inner.read_blocks(start, count, &mut &mut *buffer);
+        let len = *result.as_ref().unwrap_or(&0);
+        self.record(
+            Call::ReadBlocks { start, count },
+            buffer.len(),
+            result,
+            Some(&buffer[..len.min(buffer.len())]),
+        );
+        result
+    }
+
+    fn write_blocks(&mut self, start: u64, count: usize, buf: &dyn AsRef<[u8]>) -> Result<usize> {
+        let buffer = buf.as_ref();
+        let result = self.inner.write_blocks(start, count, buf);
+        let len = *result.as_ref().unwrap_or(&0);
+        self.record(
+            Call::WriteBlocks { start, count },
+            buffer.len(),
+            result,
+            Some(&buffer[..len.min(buffer.len())]),
+        );
+        result
+    }
+}
+
+/// A [`BlockDevice`] that plays back a trace recorded by a [`TracingDevice`].
+///
+/// Every call returns the recorded result of the next entry in the trace. Reads
+/// fill the buffer with the recorded data, if it was captured with [`Capture::Data`],
+/// and leave it untouched otherwise. Writes are checked against the recorded
+/// hash, if there is one, and fail with [`Error::IncoherentData`] if the data
+/// differs.
+///
+/// Replaying only works if the calls are made in exactly the same order as they
+/// were recorded. If a call doesn't match the next entry, or there are no entries
+/// left, the replay panics with the position in the trace, since this means that
+/// the code under test has diverged from the recording.
+pub struct ReplayDevice {
+    entries: Vec<TraceEntry>,
+    position: AtomicUsize,
+}
+
+impl ReplayDevice {
+    pub fn new(entries: Vec<TraceEntry>) -> Self {
+        Self {
+            entries,
+            position: AtomicUsize::new(0),
+        }
+    }
+
+    /// The number of entries that have not been replayed yet.
+    pub fn remaining(&self) -> usize {
+        self.entries.len() - self.position.load(Ordering::Relaxed)
+    }
+
+    fn next(&self, call: Call) -> &TraceEntry {
+        let position = self.position.fetch_add(1, Ordering::Relaxed);
+        let entry = self.entries.get(position).unwrap_or_else(|| {
+            panic!(
+                "replay diverged at entry {}: {:?}, but the trace has ended",
+                position, call
+            )
+        });
+        assert_eq!(
+            entry.call, call,
+            "replay diverged at entry {}: {:?}, but {:?} was recorded",
+            position, call, entry.call
+        );
+        entry
+    }
+
+    fn replay_read(&self, call: Call, buf: &mut dyn AsMut<[u8]>) -> Result<usize> {
+        let entry = self.next(call);
+        if let (Ok(_), Some(data)) = (entry.result, &entry.data) {
+            buf.as_mut()[..data.len()].copy_from_slice(data);
+        }
+        entry.result
+    }
+
+    fn replay_write(&self, call: Call, buf: &dyn AsRef<[u8]>) -> Result<usize> {
+        let entry = self.next(call);
+        if let (Ok(len), Some(hash)) = (entry.result, entry.hash) {
+            if crc32(&buf.as_ref()[..len]) != hash {
+                return Err(Error::IncoherentData);
+            }
+        }
+        entry.result
+    }
+}
+
+impl BlockDevice for ReplayDevice {
+    fn block_size(&self) -> usize {
+        self.next(Call::BlockSize).result.unwrap()
+    }
+
+    fn block_count(&self) -> usize {
+        self.next(Call::BlockCount).result.unwrap()
+    }
+
+    fn read_block(&self, block: u64, buf: &mut dyn AsMut<[u8]>) -> Result<usize> {
+        self.replay_read(Call::ReadBlock { block }, buf)
+    }
+
+    fn write_block(&mut self, block: u64, buf: &dyn AsRef<[u8]>) -> Result<usize> {
+        self.replay_write(Call::WriteBlock { block }, buf)
+    }
+
+    fn read_blocks(&self, start: u64, count: usize, buf: &mut dyn AsMut<[u8]>) -> Result<usize> {
+        self.replay_read(Call::ReadBlocks { start, count }, buf)
+    }
+
+    fn write_blocks(&mut self, start: u64, count: usize, buf: &dyn AsRef<[u8]>) -> Result<usize> {
+        self.replay_write(Call::WriteBlocks { start, count }, buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use crate::checksum::crc32;
+    use crate::io::block::faulty::{Fault, FaultyDevice, Operation, Trigger};
+    use crate::io::block::memory::MemoryBlockDevice;
+    use crate::io::block::trace::{Call, Capture, ReplayDevice, TraceEntry, TracingDevice};
+    use crate::io::block::BlockDevice;
+    use crate::io::{Error, ReadAt, WriteAt};
+
+    #[test]
+    fn test_tracing_records_calls() {
+        let mut device = TracingDevice::new(MemoryBlockDevice::new(4, 4), Capture::Hashes);
+        device.write_block(1, &[1_u8; 4]).unwrap();
+        assert_eq!(
+            Err(Error::NoSuchBlock),
+            device.read_block(4, &mut [0_u8; 4])
+        );
+        assert_eq!(4, device.block_count());
+
+        assert_eq!(
+            vec![
+                TraceEntry {
+                    call: Call::WriteBlock { block: 1 },
+                    len: 4,
+                    result: Ok(4),
+                    hash: Some(crc32(&[1_u8; 4])),
+                    data: None,
+                },
+                TraceEntry {
+                    call: Call::ReadBlock { block: 4 },
+                    len: 4,
+                    result: Err(Error::NoSuchBlock),
+                    hash: None,
+                    data: None,
+                },
+                TraceEntry {
+                    call: Call::BlockCount,
+                    len: 0,
+                    result: Ok(4),
+                    hash: None,
+                    data: None,
+                },
+            ],
+            device.take_trace()
+        );
+        assert!(device.trace().is_empty());
+    }
+
+    #[test]
+    fn test_tracing_multi_block() {
+        let mut device = TracingDevice::new(MemoryBlockDevice::new(4, 4), Capture::Calls);
+        device.write_at(2, &[1_u8; 8]).unwrap();
+        // head and tail are read-modify-write, the middle block is written directly
+        assert_eq!(
+            1,
+            device.count(|call| *call == Call::WriteBlocks { start: 1, count: 1 })
+        );
+        assert_eq!(
+            2,
+            device.count(|call| matches!(call, Call::ReadBlock { .. }))
+        );
+    }
+
+    #[test]
+    fn test_replay_reproduces_reads() {
+        let mut faulty = FaultyDevice::new(MemoryBlockDevice::new(4, 8));
+        faulty.inject(
+            Operation::Read,
+            Trigger::Nth(3),
+            Fault::Fail(Error::IncoherentData),
+        );
+        let mut device = TracingDevice::new(faulty, Capture::Data);
+        device.write_at(0, &[1, 2, 3, 4, 5, 6, 7, 8, 9]).unwrap();
+        let mut data = [0_u8; 6];
+        let recorded = device.read_at(1, &mut data);
+        assert_eq!(
+            Err(Error::IncoherentData),
+            device.read_at(8, &mut [0_u8; 1])
+        );
+        let trace = device.take_trace();
+
+        let mut replay = ReplayDevice::new(trace);
+        replay.write_at(0, &[1, 2, 3, 4, 5, 6, 7, 8, 9]).unwrap();
+        let mut replayed = [0_u8; 6];
+        assert_eq!(recorded, replay.read_at(1, &mut replayed));
+        assert_eq!(data, replayed);
+        assert_eq!(
+            Err(Error::IncoherentData),
+            replay.read_at(8, &mut [0_u8; 1])
+        );
+        assert_eq!(0, replay.remaining());
+    }
+
+    #[test]
+    fn test_replay_detects_different_writes() {
+        let mut device = TracingDevice::new(MemoryBlockDevice::new(4, 4), Capture::Hashes);
+        device.write_block(0, &[1_u8; 4]).unwrap();
+
+        let mut replay = ReplayDevice::new(device.take_trace());
+        assert_eq!(
+            Err(Error::IncoherentData),
+            replay.write_block(0, &[2_u8; 4])
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "replay diverged at entry 0")]
+    fn test_replay_diverged() {
+        let mut device = TracingDevice::new(MemoryBlockDevice::new(4, 4), Capture::Hashes);
+        device.write_block(0, &[1_u8; 4]).unwrap();
+
+        let replay = ReplayDevice::new(device.take_trace());
+        let _ = replay.read_block(0, &mut [0_u8; 4]);
+    }
+}
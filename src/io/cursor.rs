@@ -80,7 +80,7 @@ where
                 }
                 p as u64
             }
-            SeekFrom::Current(n) => self.pos as u64 + n as u64,
+            SeekFrom::Current(n) => self.pos + n as u64,
         };
         if new_pos >= self.inner.as_ref().len() as u64 {
             Err(Error::InvalidOffset)
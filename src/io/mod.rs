@@ -1,7 +1,5 @@
 use derive_more::Display;
 
-pub use read::*;
-
 pub mod block;
 pub mod cursor;
 pub mod macros;
@@ -74,6 +72,10 @@ pub enum Error {
     /// couldn't be completed.
     #[display(fmt = "write error")]
     WriteError,
+    /// An unexpected error occurred during the read, or the read
+    /// couldn't be completed.
+    #[display(fmt = "read error")]
+    ReadError,
 }
 
 impl core::error::Error for Error {}
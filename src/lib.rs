@@ -1,9 +1,13 @@
 #![no_std]
-#![feature(error_in_core)]
 
 extern crate alloc;
+#[cfg(test)]
+extern crate std;
 
+pub mod checksum;
 pub mod collections;
+pub mod compression;
+pub mod crypto;
 pub mod io;
 pub mod path;
 pub mod sync;